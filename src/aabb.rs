@@ -0,0 +1,101 @@
+use std::ops::Range;
+
+use crate::{ray::Ray, vector::Point3};
+
+// An axis-aligned bounding box, used to cheaply reject rays before testing the shapes inside it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    // Build the box spanned by two opposite corners, given in any order
+    pub fn new(a: Point3, b: Point3) -> Self {
+        Self {
+            min: a.min(b),
+            max: a.max(b),
+        }
+    }
+
+    // A box containing nothing, which is the identity for union
+    pub fn empty() -> Self {
+        Self {
+            min: Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    // A box containing all of space, for shapes such as infinite planes that have no bounds
+    pub fn unbounded() -> Self {
+        Self {
+            min: Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            max: Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+        }
+    }
+
+    // Whether the box has finite extent. Unbounded shapes cannot be sorted into a hierarchy
+    pub fn is_bounded(&self) -> bool {
+        (0..3).all(|axis| self.min.axis(axis).is_finite() && self.max.axis(axis).is_finite())
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    // The box where both boxes overlap, which is empty if they do not
+    pub fn intersection(self, other: Self) -> Self {
+        let (min, max) = (self.min.max(other.min), self.max.min(other.max));
+        if (0..3).any(|axis| min.axis(axis) > max.axis(axis)) {
+            Self::empty()
+        } else {
+            Self { min, max }
+        }
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        (self.min + self.max) / 2.
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        // Clamping the extent gives an empty box no area
+        let extent = (self.max - self.min).max(Point3::new(0., 0., 0.));
+        2. * (extent.x() * extent.y() + extent.y() * extent.z() + extent.z() * extent.x())
+    }
+
+    // Index of the axis along which the box is widest
+    pub fn longest_axis(&self) -> usize {
+        let extent = self.max - self.min;
+        if extent.x() > extent.y() && extent.x() > extent.z() {
+            0
+        } else if extent.y() > extent.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn hit(&self, ray: &Ray, range: &Range<f64>) -> bool {
+        // Slab method: clip the ray's parametric range against the pair of planes bounding each axis
+        let mut t_min = range.start;
+        let mut t_max = range.end;
+        for axis in 0..3 {
+            let inverse_direction = 1. / ray.direction.axis(axis);
+            let origin = ray.origin.axis(axis);
+            let mut t0 = (self.min.axis(axis) - origin) * inverse_direction;
+            let mut t1 = (self.max.axis(axis) - origin) * inverse_direction;
+            if inverse_direction < 0. {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
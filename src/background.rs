@@ -0,0 +1,219 @@
+use std::{f64::consts::PI, io, path::Path};
+
+use rand::{Rng, RngCore};
+
+use crate::{
+    canvas::Canvas,
+    input,
+    vector::{Color3, Vector3},
+};
+
+const TWO_PI: f64 = 2. * PI;
+
+// A direction toward the background chosen by importance sampling
+pub struct BackgroundSample {
+    pub direction: Vector3,
+    pub radiance: Color3,
+    pub pdf: f64, // per unit solid angle
+}
+
+// The light arriving from infinitely far away along rays that escape the scene
+pub trait Background: Send + Sync {
+    fn color(&self, direction: Vector3) -> Color3;
+
+    // Choose a direction in proportion to the light arriving from it. Backgrounds that are smooth
+    // enough to be found by ordinary scattering return None and are never sampled directly
+    fn sample(&self, _rng: &mut dyn RngCore) -> Option<BackgroundSample> {
+        None
+    }
+
+    // The probability density, per unit solid angle, with which `sample` picks a direction
+    fn pdf(&self, _direction: Vector3) -> f64 {
+        0.
+    }
+}
+
+pub struct SolidBackground {
+    pub color: Color3,
+}
+
+impl Background for SolidBackground {
+    fn color(&self, _direction: Vector3) -> Color3 {
+        self.color
+    }
+}
+
+// Blends linearly from the bottom color straight down to the top color straight up
+pub struct GradientBackground {
+    pub bottom: Color3,
+    pub top: Color3,
+}
+
+impl GradientBackground {
+    // The pale blue sky used when a scene does not specify a background
+    pub fn sky() -> Self {
+        Self {
+            bottom: Color3::new(0.75, 0.85, 1.),
+            top: Color3::new(0.25, 0.55, 1.),
+        }
+    }
+}
+
+impl Background for GradientBackground {
+    fn color(&self, direction: Vector3) -> Color3 {
+        let a = (direction.unit().y() + 1.) * 0.5;
+        self.bottom * (1. - a) + self.top * a
+    }
+}
+
+// An image covering the whole sphere of directions in equirectangular (latitude-longitude)
+// projection, such as a captured HDR panorama. The top row of the image is straight up, and the
+// center of the image looks down the negative z axis before rotation
+pub struct EnvironmentMap {
+    image: Canvas,
+    intensity: f64,
+    rotation: f64, // radians about the y axis
+    // Cumulative distributions for importance sampling: over rows, and over pixels within each
+    // row, both normalized to end at 1
+    row_cdf: Vec<f64>,
+    pixel_cdf: Vec<f64>,
+    total_weight: f64,
+}
+
+impl EnvironmentMap {
+    // Rotation is given in degrees about the vertical axis
+    pub fn new(image: Canvas, intensity: f64, rotation: f64) -> Self {
+        // Weight each pixel by its luminance and by the solid angle it covers, which shrinks toward
+        // the poles of the projection
+        let (width, height) = (image.width as usize, image.height as usize);
+        let mut row_cdf = Vec::with_capacity(height);
+        let mut pixel_cdf = Vec::with_capacity(width * height);
+        let mut total = 0.;
+        for y in 0..image.height {
+            let sin_theta = ((y as f64 + 0.5) / height as f64 * PI).sin();
+            let mut row_total = 0.;
+            for color in image.row(y) {
+                row_total += luminance(*color).max(0.) * sin_theta;
+                pixel_cdf.push(row_total);
+            }
+            let row = &mut pixel_cdf[y as usize * width..];
+            for value in row.iter_mut() {
+                *value = if row_total > 0. {
+                    *value / row_total
+                } else {
+                    1.
+                };
+            }
+            total += row_total;
+            row_cdf.push(total);
+        }
+        for value in row_cdf.iter_mut() {
+            *value = if total > 0. { *value / total } else { 1. };
+        }
+
+        Self {
+            image,
+            intensity,
+            rotation: rotation.to_radians(),
+            row_cdf,
+            pixel_cdf,
+            total_weight: total,
+        }
+    }
+
+    pub fn load(path: &Path, intensity: f64, rotation: f64) -> io::Result<Self> {
+        let image = input::read_image(path)?;
+        if image.width == 0 || image.height == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "environment image is empty",
+            ));
+        }
+        Ok(Self::new(image, intensity, rotation))
+    }
+
+    // Probability that importance sampling picks a given pixel
+    fn pixel_probability(&self, x: u32, y: u32) -> f64 {
+        let (x, y, width) = (x as usize, y as usize, self.image.width as usize);
+        let row = self.row_cdf[y] - if y > 0 { self.row_cdf[y - 1] } else { 0. };
+        let row_pixels = &self.pixel_cdf[y * width..(y + 1) * width];
+        let pixel = row_pixels[x] - if x > 0 { row_pixels[x - 1] } else { 0. };
+        row * pixel
+    }
+
+    // Map a point in image space, with u and v from 0 to 1, to the direction it covers
+    fn direction(&self, u: f64, v: f64) -> Vector3 {
+        let phi = (u - 0.5) * TWO_PI - self.rotation;
+        let theta = v * PI;
+        Vector3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        )
+    }
+
+    // Map a direction to the pixel of the image that covers it
+    fn pixel(&self, direction: Vector3) -> (u32, u32) {
+        let direction = direction.unit();
+        let phi = direction.x().atan2(-direction.z()) + self.rotation;
+        let theta = direction.y().clamp(-1., 1.).acos();
+        let u = (phi / TWO_PI + 0.5).rem_euclid(1.);
+        let v = theta / PI;
+        let x = ((u * self.image.width as f64) as u32).min(self.image.width - 1);
+        let y = ((v * self.image.height as f64) as u32).min(self.image.height - 1);
+        (x, y)
+    }
+}
+
+impl Background for EnvironmentMap {
+    fn color(&self, direction: Vector3) -> Color3 {
+        let (x, y) = self.pixel(direction);
+        *self.image.get_pixel(x, y) * self.intensity
+    }
+
+    fn sample(&self, rng: &mut dyn RngCore) -> Option<BackgroundSample> {
+        if self.total_weight <= 0. {
+            return None;
+        }
+        // Choose a row, then a pixel within that row, then a point within that pixel
+        let width = self.image.width as usize;
+        let y = sample_cdf(&self.row_cdf, rng.gen());
+        let x = sample_cdf(&self.pixel_cdf[y * width..(y + 1) * width], rng.gen());
+        let u = (x as f64 + rng.gen::<f64>()) / self.image.width as f64;
+        let v = (y as f64 + rng.gen::<f64>()) / self.image.height as f64;
+
+        let direction = self.direction(u, v);
+        let pdf = self.pdf(direction);
+        if pdf <= 0. {
+            return None;
+        }
+        Some(BackgroundSample {
+            direction,
+            radiance: self.color(direction),
+            pdf,
+        })
+    }
+
+    fn pdf(&self, direction: Vector3) -> f64 {
+        let direction = direction.unit();
+        let sin_theta = (1. - direction.y() * direction.y()).max(0.).sqrt();
+        if self.total_weight <= 0. || sin_theta <= 0. {
+            return 0.;
+        }
+        // Pixels are sampled uniformly in image space, which maps onto the sphere with a density
+        // proportional to 1 / sin(theta)
+        let (x, y) = self.pixel(direction);
+        let pixels = self.image.width as f64 * self.image.height as f64;
+        self.pixel_probability(x, y) * pixels / (2. * PI * PI * sin_theta)
+    }
+}
+
+// Index of the first entry of a cumulative distribution that exceeds `u`, which picks each entry
+// with the probability it adds to the distribution
+fn sample_cdf(cdf: &[f64], u: f64) -> usize {
+    cdf.partition_point(|&value| value <= u).min(cdf.len() - 1)
+}
+
+fn luminance(color: Color3) -> f64 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
+}
@@ -0,0 +1,328 @@
+use std::fmt;
+use std::ops::Range;
+use std::option::Option;
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable, World};
+use crate::ray::Ray;
+use crate::vector::Point3;
+
+// A node in a bounding volume hierarchy. Each node holds two subtrees and the box enclosing both,
+// so a ray that misses the box can skip everything below it
+pub struct BvhNode {
+    left: Box<dyn Hittable>,
+    right: Box<dyn Hittable>,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    // Recursively split the shapes in half along the longest axis of their centroids until each
+    // subtree holds a single shape
+    pub fn build(mut shapes: Vec<Box<dyn Hittable>>) -> Box<dyn Hittable> {
+        match shapes.len() {
+            0 => return Box::new(World::new()),
+            1 => return shapes.pop().unwrap(),
+            _ => (),
+        }
+
+        let centroid_bounds = shapes.iter().fold(Aabb::empty(), |bounds, shape| {
+            let centroid = shape.bounding_box().centroid();
+            bounds.union(Aabb::new(centroid, centroid))
+        });
+        let axis = centroid_bounds.longest_axis();
+        shapes.sort_by(|a, b| {
+            let a = a.bounding_box().centroid().axis(axis);
+            let b = b.bounding_box().centroid().axis(axis);
+            a.total_cmp(&b)
+        });
+
+        let right_shapes = shapes.split_off(shapes.len() / 2);
+        let left = Self::build(shapes);
+        let right = Self::build(right_shapes);
+        let bbox = left.bounding_box().union(right.bounding_box());
+        Box::new(Self { left, right, bbox })
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>> {
+        if !self.bbox.hit(ray, range) {
+            return None;
+        }
+
+        // Anything in the right subtree only matters if it is closer than the best hit on the left
+        let left_hit = self.left.hit(ray, range);
+        let right_range = Range {
+            start: range.start,
+            end: left_hit.map_or(range.end, |hit| hit.distance),
+        };
+        self.right.hit(ray, &right_range).or(left_hit)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+// Statistics describing the hierarchy produced by a SahBuilder
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BuildStats {
+    pub build_time: Duration,
+    pub nodes: usize,
+    pub leaves: usize,
+    pub depth: usize,
+    // Expected cost of tracing a ray through the tree, in units of a single shape intersection
+    pub traversal_cost: f64,
+}
+
+impl fmt::Display for BuildStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "built BVH in {:.2?}: {} nodes, {} leaves, depth {}, estimated cost {:.2}",
+            self.build_time, self.nodes, self.leaves, self.depth, self.traversal_cost
+        )
+    }
+}
+
+// A shape waiting to be placed in the tree, with its bounds cached so they are only computed once
+struct Primitive {
+    shape: Box<dyn Hittable>,
+    bbox: Aabb,
+    centroid: Point3,
+}
+
+#[derive(Copy, Clone)]
+struct Bin {
+    bbox: Aabb,
+    count: usize,
+}
+
+// Bins spread evenly over the centroids along one axis
+#[derive(Copy, Clone)]
+struct Binning {
+    axis: usize,
+    min: f64,
+    extent: f64,
+    count: usize,
+}
+
+impl Binning {
+    fn index(&self, centroid: Point3) -> usize {
+        let offset = (centroid.axis(self.axis) - self.min) / self.extent;
+        ((offset * self.count as f64) as usize).min(self.count - 1)
+    }
+}
+
+// A split plane at the boundary below bin `first_right`, with its estimated cost. Primitives are
+// sent to either side by the bin they were counted in when the cost was estimated, rather than by
+// comparing them with the plane's position, which rounding could put on the other side
+#[derive(Copy, Clone)]
+struct Split {
+    binning: Binning,
+    first_right: usize,
+    cost: f64,
+}
+
+// Builds a BVH by choosing, at every node, the split that minimizes the surface area heuristic:
+// the probability of a ray hitting each child (proportional to its surface area) times the number
+// of shapes it holds. Candidate splits are evaluated at the boundaries of a fixed number of bins
+pub struct SahBuilder {
+    pub bins: usize,
+    pub max_leaf_size: usize,
+    pub traversal_cost: f64,
+    pub intersection_cost: f64,
+}
+
+impl Default for SahBuilder {
+    fn default() -> Self {
+        Self {
+            bins: 16,
+            max_leaf_size: 4,
+            traversal_cost: 1.,
+            intersection_cost: 1.,
+        }
+    }
+}
+
+impl SahBuilder {
+    pub fn build(&self, shapes: Vec<Box<dyn Hittable>>) -> (Box<dyn Hittable>, BuildStats) {
+        let start = Instant::now();
+        let primitives: Vec<Primitive> = shapes
+            .into_iter()
+            .map(|shape| {
+                let bbox = shape.bounding_box();
+                Primitive {
+                    shape,
+                    bbox,
+                    centroid: bbox.centroid(),
+                }
+            })
+            .collect();
+
+        let mut stats = BuildStats {
+            build_time: Duration::default(),
+            nodes: 0,
+            leaves: 0,
+            depth: 0,
+            traversal_cost: 0.,
+        };
+        let root_area = primitives
+            .iter()
+            .fold(Aabb::empty(), |bounds, p| bounds.union(p.bbox))
+            .surface_area();
+        let root = self.build_node(primitives, root_area, 1, &mut stats);
+        stats.build_time = start.elapsed();
+        (root, stats)
+    }
+
+    fn build_node(
+        &self,
+        mut primitives: Vec<Primitive>,
+        root_area: f64,
+        depth: usize,
+        stats: &mut BuildStats,
+    ) -> Box<dyn Hittable> {
+        let bounds = primitives
+            .iter()
+            .fold(Aabb::empty(), |bounds, p| bounds.union(p.bbox));
+        // Probability that a ray passing through the root also passes through this node
+        let hit_probability = if root_area > 0. {
+            bounds.surface_area() / root_area
+        } else {
+            1.
+        };
+        stats.nodes += 1;
+        stats.depth = stats.depth.max(depth);
+
+        let leaf_cost = self.intersection_cost * primitives.len() as f64;
+        let split = self.best_split(&primitives, &bounds);
+        let make_leaf = primitives.len() <= 1
+            || (primitives.len() <= self.max_leaf_size
+                && split.is_none_or(|split| split.cost >= leaf_cost));
+
+        if make_leaf {
+            stats.leaves += 1;
+            stats.traversal_cost += hit_probability * leaf_cost;
+            return if primitives.len() == 1 {
+                primitives.pop().unwrap().shape
+            } else {
+                let mut leaf = World::new();
+                for primitive in primitives {
+                    leaf.add(primitive.shape);
+                }
+                Box::new(leaf)
+            };
+        }
+        stats.traversal_cost += hit_probability * self.traversal_cost;
+
+        let mut right_primitives = match split {
+            Some(split) => {
+                let (left, right) = primitives
+                    .into_iter()
+                    .partition(|p| split.binning.index(p.centroid) < split.first_right);
+                primitives = left;
+                right
+            }
+            None => Vec::new(),
+        };
+        // If the centroids are indistinguishable, or a split somehow leaves one side empty, fall
+        // back to splitting at the median, so that every node is smaller than its parent
+        if primitives.is_empty() || right_primitives.is_empty() {
+            primitives.append(&mut right_primitives);
+            if let Some(split) = split {
+                let axis = split.binning.axis;
+                primitives.sort_by(|a, b| a.centroid.axis(axis).total_cmp(&b.centroid.axis(axis)));
+            }
+            let half = primitives.len() / 2;
+            right_primitives = primitives.split_off(half);
+        }
+
+        let left = self.build_node(primitives, root_area, depth + 1, stats);
+        let right = self.build_node(right_primitives, root_area, depth + 1, stats);
+        Box::new(BvhNode {
+            left,
+            right,
+            bbox: bounds,
+        })
+    }
+
+    // Find the cheapest split plane
+    fn best_split(&self, primitives: &[Primitive], bounds: &Aabb) -> Option<Split> {
+        let centroid_bounds = primitives.iter().fold(Aabb::empty(), |bounds, p| {
+            bounds.union(Aabb::new(p.centroid, p.centroid))
+        });
+        let parent_area = bounds.surface_area();
+        let bin_count = self.bins.max(2);
+        let mut best: Option<Split> = None;
+
+        for axis in 0..3 {
+            let min = centroid_bounds.min.axis(axis);
+            let extent = centroid_bounds.max.axis(axis) - min;
+            if extent <= 0. {
+                continue;
+            }
+            let binning = Binning {
+                axis,
+                min,
+                extent,
+                count: bin_count,
+            };
+
+            let mut bins = vec![
+                Bin {
+                    bbox: Aabb::empty(),
+                    count: 0
+                };
+                bin_count
+            ];
+            for primitive in primitives {
+                let index = binning.index(primitive.centroid);
+                bins[index].bbox = bins[index].bbox.union(primitive.bbox);
+                bins[index].count += 1;
+            }
+
+            // Sweep from the right to find the bounds and counts of every suffix of bins, then
+            // sweep from the left to evaluate each boundary as a split plane
+            let mut right_area = vec![0.; bin_count];
+            let mut right_count = vec![0; bin_count];
+            let mut right_bounds = Aabb::empty();
+            let mut count = 0;
+            for i in (1..bin_count).rev() {
+                right_bounds = right_bounds.union(bins[i].bbox);
+                count += bins[i].count;
+                right_area[i] = if count > 0 {
+                    right_bounds.surface_area()
+                } else {
+                    0.
+                };
+                right_count[i] = count;
+            }
+
+            let mut left_bounds = Aabb::empty();
+            let mut left_count = 0;
+            for i in 0..bin_count - 1 {
+                left_bounds = left_bounds.union(bins[i].bbox);
+                left_count += bins[i].count;
+                if left_count == 0 || right_count[i + 1] == 0 {
+                    continue;
+                }
+                let cost = self.traversal_cost
+                    + self.intersection_cost
+                        * (left_bounds.surface_area() * left_count as f64
+                            + right_area[i + 1] * right_count[i + 1] as f64)
+                        / parent_area;
+                if best.is_none_or(|best| cost < best.cost) {
+                    best = Some(Split {
+                        binning,
+                        first_right: i + 1,
+                        cost,
+                    });
+                }
+            }
+        }
+        best
+    }
+}
@@ -0,0 +1,231 @@
+use std::{iter::Iterator, slice::ChunksExactMut};
+
+use crate::vector::Color3;
+
+// A rectangular image of linear color values, stored contiguously in row-major order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Canvas {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<Color3>,
+}
+
+impl Canvas {
+    // Create a canvas with every pixel set to black
+    pub fn new(width: u32, height: u32) -> Self {
+        Self::filled(width, height, Color3::new(0., 0., 0.))
+    }
+
+    pub fn filled(width: u32, height: u32, color: Color3) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![color; width as usize * height as usize],
+        }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x < self.width && y < self.height
+    }
+
+    // Panics if the pixel is outside the canvas
+    pub fn get_pixel(&self, x: u32, y: u32) -> &Color3 {
+        self.get(x, y).unwrap_or_else(|| {
+            panic!(
+                "pixel ({}, {}) is outside the {}x{} canvas",
+                x, y, self.width, self.height
+            )
+        })
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Option<&Color3> {
+        if self.contains(x, y) {
+            Some(&self.pixels[self.index(x, y)])
+        } else {
+            None
+        }
+    }
+
+    // Panics if the pixel is outside the canvas
+    pub fn put_pixel(&mut self, x: u32, y: u32, color: Color3) {
+        match self.get_mut(x, y) {
+            Some(pixel) => *pixel = color,
+            None => panic!(
+                "pixel ({}, {}) is outside the {}x{} canvas",
+                x, y, self.width, self.height
+            ),
+        }
+    }
+
+    pub fn get_mut(&mut self, x: u32, y: u32) -> Option<&mut Color3> {
+        if self.contains(x, y) {
+            let index = self.index(x, y);
+            Some(&mut self.pixels[index])
+        } else {
+            None
+        }
+    }
+
+    /// # Safety
+    /// `x` and `y` must lie within the canvas
+    pub unsafe fn get_pixel_unchecked(&self, x: u32, y: u32) -> &Color3 {
+        self.pixels.get_unchecked(self.index(x, y))
+    }
+
+    /// # Safety
+    /// `x` and `y` must lie within the canvas
+    pub unsafe fn put_pixel_unchecked(&mut self, x: u32, y: u32, color: Color3) {
+        let index = self.index(x, y);
+        *self.pixels.get_unchecked_mut(index) = color;
+    }
+
+    // All pixels in row-major order
+    pub fn pixels(&self) -> &[Color3] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Color3] {
+        &mut self.pixels
+    }
+
+    pub fn row(&self, y: u32) -> &[Color3] {
+        let start = self.index(0, y);
+        &self.pixels[start..start + self.width as usize]
+    }
+
+    pub fn row_mut(&mut self, y: u32) -> &mut [Color3] {
+        let start = self.index(0, y);
+        let width = self.width as usize;
+        &mut self.pixels[start..start + width]
+    }
+
+    // Split the canvas into horizontal bands of up to `rows` rows each, paired with the index of
+    // their first row. The bands are disjoint, so each can be handed to a different thread
+    pub fn bands_mut(&mut self, rows: u32) -> impl Iterator<Item = (u32, &mut [Color3])> {
+        let band_size = (self.width as usize * rows as usize).max(1);
+        self.pixels
+            .chunks_mut(band_size)
+            .enumerate()
+            .map(move |(i, band)| (i as u32 * rows, band))
+    }
+
+    pub fn rows_mut(&mut self) -> ChunksExactMut<'_, Color3> {
+        self.pixels.chunks_exact_mut((self.width as usize).max(1))
+    }
+
+    // Iterate over every pixel along with its coordinates, in row-major order
+    pub fn iter(&self) -> impl Iterator<Item = (u32, u32, &Color3)> {
+        let width = self.width.max(1);
+        self.pixels
+            .iter()
+            .enumerate()
+            .map(move |(i, color)| (i as u32 % width, i as u32 / width, color))
+    }
+
+    // Copy out the region with its top left corner at (x, y), clipped to the canvas
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Canvas {
+        let width = width.min(self.width.saturating_sub(x));
+        let height = height.min(self.height.saturating_sub(y));
+        let mut cropped = Canvas::new(width, height);
+        // A region starting past the right edge has no pixels, and no row of it can be indexed
+        if width == 0 {
+            return cropped;
+        }
+        for j in 0..height {
+            let start = self.index(x, y + j);
+            cropped
+                .row_mut(j)
+                .copy_from_slice(&self.pixels[start..start + width as usize]);
+        }
+        cropped
+    }
+
+    // Copy another canvas onto this one with its top left corner at (x, y). Any part of the
+    // source that falls outside this canvas is discarded
+    pub fn blit(&mut self, source: &Canvas, x: u32, y: u32) {
+        let width = source.width.min(self.width.saturating_sub(x)) as usize;
+        let height = source.height.min(self.height.saturating_sub(y));
+        if width == 0 {
+            return;
+        }
+        for j in 0..height {
+            let start = self.index(x, y + j);
+            self.pixels[start..start + width].copy_from_slice(&source.row(j)[..width]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A canvas where each pixel's red channel is its index, so copies can be checked exactly
+    fn numbered(width: u32, height: u32) -> Canvas {
+        let mut canvas = Canvas::new(width, height);
+        for (i, pixel) in canvas.pixels_mut().iter_mut().enumerate() {
+            *pixel = Color3::new(i as f64, 0., 0.);
+        }
+        canvas
+    }
+
+    #[test]
+    fn crop_inside() {
+        let canvas = numbered(10, 10);
+        let cropped = canvas.crop(2, 3, 4, 2);
+        assert_eq!((cropped.width, cropped.height), (4, 2));
+        assert_eq!(cropped.get_pixel(0, 0), canvas.get_pixel(2, 3));
+        assert_eq!(cropped.get_pixel(3, 1), canvas.get_pixel(5, 4));
+    }
+
+    #[test]
+    fn crop_clips_to_edges() {
+        let canvas = numbered(10, 10);
+        let cropped = canvas.crop(8, 7, 5, 5);
+        assert_eq!((cropped.width, cropped.height), (2, 3));
+        assert_eq!(cropped.get_pixel(1, 2), canvas.get_pixel(9, 9));
+    }
+
+    #[test]
+    fn crop_outside_is_empty() {
+        let canvas = numbered(10, 10);
+        for (x, y) in [(15, 9), (10, 0), (0, 10), (12, 12)] {
+            let cropped = canvas.crop(x, y, 5, 1);
+            assert_eq!(cropped.width * cropped.height, 0);
+            assert!(cropped.pixels().is_empty());
+        }
+    }
+
+    #[test]
+    fn blit_inside() {
+        let mut canvas = Canvas::new(10, 10);
+        let source = numbered(3, 2);
+        canvas.blit(&source, 4, 5);
+        assert_eq!(canvas.get_pixel(4, 5), source.get_pixel(0, 0));
+        assert_eq!(canvas.get_pixel(6, 6), source.get_pixel(2, 1));
+        assert_eq!(*canvas.get_pixel(3, 5), Color3::new(0., 0., 0.));
+        assert_eq!(*canvas.get_pixel(7, 5), Color3::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn blit_clips_to_edges() {
+        let mut canvas = Canvas::new(10, 10);
+        let source = numbered(4, 4);
+        canvas.blit(&source, 8, 8);
+        assert_eq!(canvas.get_pixel(8, 8), source.get_pixel(0, 0));
+        assert_eq!(canvas.get_pixel(9, 9), source.get_pixel(1, 1));
+    }
+
+    #[test]
+    fn blit_outside_does_nothing() {
+        let mut canvas = Canvas::new(10, 10);
+        let source = numbered(5, 1);
+        for (x, y) in [(15, 9), (10, 0), (0, 10), (12, 12)] {
+            canvas.blit(&source, x, y);
+        }
+        assert_eq!(canvas, Canvas::new(10, 10));
+    }
+}
@@ -0,0 +1,113 @@
+use std::ops::Range;
+use std::option::Option;
+use std::vec::Vec;
+
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable, Interval};
+use crate::ray::Ray;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CsgOperation {
+    // Everything inside either shape
+    Union,
+    // Only what is inside both shapes
+    Intersection,
+    // What is inside the first shape but not the second
+    Difference,
+}
+
+impl CsgOperation {
+    fn inside(self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_a || in_b,
+            CsgOperation::Intersection => in_a && in_b,
+            CsgOperation::Difference => in_a && !in_b,
+        }
+    }
+}
+
+// A solid made by combining two closed shapes. Each part of its surface keeps the material of the
+// shape it comes from, and its normals point out of the combined solid, so that materials such as
+// glass that care which side they are hit from refract correctly through it
+pub struct Csg {
+    pub operation: CsgOperation,
+    pub a: Box<dyn Hittable>,
+    pub b: Box<dyn Hittable>,
+}
+
+// A point along the ray where it crosses the surface of one of the two shapes
+struct Crossing<'a> {
+    hit: Hit<'a>,
+    from_a: bool,
+    entering: bool,
+}
+
+impl Hittable for Csg {
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>> {
+        // The surface of the solid is made up of the ends of its intervals, so the closest hit is
+        // the first of those in range, whether the ray is entering the solid or leaving it
+        self.intervals(ray)
+            .into_iter()
+            .flat_map(|interval| [interval.enter, interval.exit])
+            .find(|hit| range.contains(&hit.distance))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        match self.operation {
+            CsgOperation::Union => self.a.bounding_box().union(self.b.bounding_box()),
+            CsgOperation::Intersection => self.a.bounding_box().intersection(self.b.bounding_box()),
+            CsgOperation::Difference => self.a.bounding_box(),
+        }
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval<'_>> {
+        // Merge the entries and exits of both shapes in order along the ray, and keep the
+        // crossings where the combination changes from outside to inside or back
+        let mut crossings: Vec<Crossing> = Vec::new();
+        for (shape, from_a) in [(&self.a, true), (&self.b, false)] {
+            for interval in shape.intervals(ray) {
+                crossings.push(Crossing {
+                    hit: interval.enter,
+                    from_a,
+                    entering: true,
+                });
+                crossings.push(Crossing {
+                    hit: interval.exit,
+                    from_a,
+                    entering: false,
+                });
+            }
+        }
+        crossings.sort_by(|x, y| x.hit.distance.total_cmp(&y.hit.distance));
+
+        let mut intervals = Vec::new();
+        let (mut in_a, mut in_b) = (false, false);
+        let mut entry: Option<Hit> = None;
+        for crossing in crossings {
+            let was_inside = self.operation.inside(in_a, in_b);
+            if crossing.from_a {
+                in_a = crossing.entering;
+            } else {
+                in_b = crossing.entering;
+            }
+            let inside = self.operation.inside(in_a, in_b);
+            if inside == was_inside {
+                continue;
+            }
+
+            // Where the second shape is cut away, its surface bounds the solid from the other
+            // side, so its normals are turned around to point out of the solid
+            let mut hit = crossing.hit;
+            if self.operation == CsgOperation::Difference && !crossing.from_a {
+                hit.normal = -hit.normal;
+                hit.geometric_normal = -hit.geometric_normal;
+            }
+            if inside {
+                entry = Some(hit);
+            } else if let Some(enter) = entry.take() {
+                intervals.push(Interval { enter, exit: hit });
+            }
+        }
+        intervals
+    }
+}
@@ -0,0 +1,130 @@
+use std::ops::Range;
+use std::option::Option;
+
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable};
+use crate::material::Material;
+use crate::matrix::Matrix4;
+use crate::ray::Ray;
+use crate::vector::{Point3, Vector3};
+
+// Axes along which texture coordinates run on the faces perpendicular to each axis, chosen so that
+// v runs up the sides of an upright box
+const FACE_UV_AXES: [(usize, usize); 3] = [(2, 1), (0, 2), (0, 1)];
+
+// A rectangular box, which may be rotated. Each face is textured separately, with texture
+// coordinates from 0 to 1 across it
+pub struct Cuboid {
+    pub center: Point3,
+    // Half the length of each side
+    pub half_size: Vector3,
+    // Unit directions of the box's edges, which must be perpendicular to each other
+    pub axes: [Vector3; 3],
+    pub material: Box<dyn Material>,
+}
+
+impl Cuboid {
+    // An axis-aligned box spanned by two opposite corners, given in any order
+    pub fn new(a: Point3, b: Point3, material: Box<dyn Material>) -> Self {
+        let bbox = Aabb::new(a, b);
+        Self {
+            center: bbox.centroid(),
+            half_size: (bbox.max - bbox.min) / 2.,
+            axes: [
+                Vector3::new(1., 0., 0.),
+                Vector3::new(0., 1., 0.),
+                Vector3::new(0., 0., 1.),
+            ],
+            material,
+        }
+    }
+
+    // Rotate the box about its center
+    pub fn rotated(mut self, rotation: &Matrix4) -> Self {
+        for axis in &mut self.axes {
+            *axis = rotation.transform_vector(*axis).unit();
+        }
+        self
+    }
+
+    // Outward normal and texture coordinates of the face perpendicular to `axis`, on the side
+    // given by `sign`, at a point given in the box's own coordinates
+    fn face(&self, axis: usize, sign: f64, local: Vector3) -> (Vector3, (f64, f64)) {
+        let (u_axis, v_axis) = FACE_UV_AXES[axis];
+        // Flip u where needed so that textures read the right way round from outside the box
+        let handedness = Vector3::new(-1., -1., 1.).axis(axis) * sign;
+        let u = handedness * local.axis(u_axis) / (2. * self.half_size.axis(u_axis)) + 0.5;
+        let v = local.axis(v_axis) / (2. * self.half_size.axis(v_axis)) + 0.5;
+        (self.axes[axis] * sign, (u, v))
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>> {
+        // Slab method in the box's own coordinates, remembering which slab the ray enters and
+        // leaves the box through
+        let offset = ray.origin - self.center;
+        let origin = Vector3::new(
+            offset.dot(self.axes[0]),
+            offset.dot(self.axes[1]),
+            offset.dot(self.axes[2]),
+        );
+        let direction = Vector3::new(
+            ray.direction.dot(self.axes[0]),
+            ray.direction.dot(self.axes[1]),
+            ray.direction.dot(self.axes[2]),
+        );
+        let (mut t_near, mut near_axis) = (f64::NEG_INFINITY, 0);
+        let (mut t_far, mut far_axis) = (f64::INFINITY, 0);
+        for axis in 0..3 {
+            let inverse_direction = 1. / direction.axis(axis);
+            let half = self.half_size.axis(axis);
+            let mut t0 = (-half - origin.axis(axis)) * inverse_direction;
+            let mut t1 = (half - origin.axis(axis)) * inverse_direction;
+            if inverse_direction < 0. {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            if t0 > t_near {
+                t_near = t0;
+                near_axis = axis;
+            }
+            if t1 < t_far {
+                t_far = t1;
+                far_axis = axis;
+            }
+        }
+        if t_far < t_near {
+            return None;
+        }
+
+        // Take the entry point if it is in range, and otherwise the exit point for rays that
+        // start inside the box
+        let (t, axis, sign) = if range.contains(&t_near) {
+            (t_near, near_axis, -direction.axis(near_axis).signum())
+        } else if range.contains(&t_far) {
+            (t_far, far_axis, direction.axis(far_axis).signum())
+        } else {
+            return None;
+        };
+        let (normal, uv) = self.face(axis, sign, origin + direction * t);
+        Some(Hit {
+            point: ray.at(t),
+            normal,
+            geometric_normal: normal,
+            distance: t,
+            uv,
+            barycentric: None,
+            color: None,
+            material: self.material.as_ref(),
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // Along each world axis the box extends by the projections of its three half sides
+        let extent = (0..3).fold(Vector3::new(0., 0., 0.), |extent, axis| {
+            let side = self.axes[axis] * self.half_size.axis(axis);
+            extent + Vector3::new(side.x().abs(), side.y().abs(), side.z().abs())
+        });
+        Aabb::new(self.center - extent, self.center + extent)
+    }
+}
@@ -0,0 +1,384 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use ::gltf::{camera::Projection, image, mesh::Mode, Document, Node};
+
+use crate::{
+    canvas::Canvas,
+    hittable::World,
+    material::{Material, PbrMaterial},
+    matrix::Matrix4,
+    mesh::{self, Mesh, MeshData, MeshError, MeshTriangle},
+    texture::ImageTexture,
+    vector::{Color3, Point3, Vector3},
+};
+
+// The contents of a glTF file: all of its geometry, and the first camera if it has one
+pub struct GltfScene {
+    pub world: World,
+    pub camera: Option<GltfCamera>,
+}
+
+// Where a glTF camera is and how it is pointed, in world space
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GltfCamera {
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub up: Vector3,
+    pub vertical_fov: f64, // degrees
+    pub aspect_ratio: Option<f64>,
+}
+
+// Load a .gltf or .glb file, including any buffers and images it embeds or refers to. Every mesh
+// in the default scene is placed by the transforms of the nodes above it and merged into one
+pub fn load(path: &Path) -> Result<GltfScene, MeshError> {
+    let (mesh, camera) = import(path)?;
+    let mut world = World::new();
+    if !mesh.triangles.is_empty() {
+        world.add(Box::new(Mesh::new(mesh)));
+    }
+    Ok(GltfScene { world, camera })
+}
+
+// Load only the geometry and materials of a .gltf or .glb file
+pub fn load_mesh(path: &Path) -> Result<MeshData, MeshError> {
+    import(path).map(|(mesh, _)| mesh)
+}
+
+fn import(path: &Path) -> Result<(MeshData, Option<GltfCamera>), MeshError> {
+    let invalid = |message: String| MeshError::Invalid {
+        file: path.to_path_buf(),
+        message,
+    };
+    let (document, buffers, images) =
+        ::gltf::import(path).map_err(|err| invalid(err.to_string()))?;
+
+    let mut loader = Loader {
+        document: &document,
+        buffers: &buffers,
+        images: &images,
+        mesh: MeshData::default(),
+        materials: HashMap::new(),
+        textures: HashMap::new(),
+        camera: None,
+    };
+    let scene = match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => scene,
+        None => return Err(invalid("file has no scenes".into())),
+    };
+    for node in scene.nodes() {
+        loader
+            .add_node(&node, Matrix4::identity())
+            .map_err(invalid)?;
+    }
+    let camera = loader.camera.map(|(_, camera)| camera);
+    Ok((loader.mesh, camera))
+}
+
+struct Loader<'a> {
+    document: &'a Document,
+    buffers: &'a [::gltf::buffer::Data],
+    images: &'a [image::Data],
+    mesh: MeshData,
+    // Index into the mesh's materials of each glTF material used so far, with None for the
+    // default material
+    materials: HashMap<Option<usize>, usize>,
+    // Textures by image index and whether the image stores display rather than linear values
+    textures: HashMap<(usize, bool), Arc<ImageTexture>>,
+    // The camera with the lowest index found so far, along with that index
+    camera: Option<(usize, GltfCamera)>,
+}
+
+impl Loader<'_> {
+    fn add_node(&mut self, node: &Node, parent: Matrix4) -> Result<(), String> {
+        let columns = node.transform().matrix().map(|c| c.map(|v| v as f64));
+        let transform = parent * Matrix4::from_columns(columns);
+
+        if let Some(camera) = node.camera() {
+            if let Projection::Perspective(perspective) = camera.projection() {
+                if self
+                    .camera
+                    .as_ref()
+                    .is_none_or(|(i, _)| camera.index() < *i)
+                {
+                    // glTF cameras look down their negative z axis with y up
+                    let look_from = transform.transform_point(Point3::new(0., 0., 0.));
+                    let forward = transform.transform_vector(Vector3::new(0., 0., -1.));
+                    self.camera = Some((
+                        camera.index(),
+                        GltfCamera {
+                            look_from,
+                            look_at: look_from + forward.unit(),
+                            up: transform.transform_vector(Vector3::new(0., 1., 0.)),
+                            vertical_fov: (perspective.yfov() as f64).to_degrees(),
+                            aspect_ratio: perspective.aspect_ratio().map(|a| a as f64),
+                        },
+                    ));
+                }
+            }
+        }
+
+        if let Some(mesh) = node.mesh() {
+            // Normals are carried by the inverse transpose, which keeps them perpendicular to the
+            // surface under non-uniform scaling
+            let normal_transform = transform
+                .inverse()
+                .ok_or_else(|| format!("node {} has a singular transform", node.index()))?
+                .transpose();
+            // Triangles face the side from which their vertices run counter-clockwise, so a
+            // transform that mirrors them must also reverse their vertices to keep them facing out
+            let mirrored = transform.linear_determinant() < 0.;
+            for primitive in mesh.primitives() {
+                // Points and lines have no surface to render
+                if primitive.mode() != Mode::Triangles {
+                    continue;
+                }
+                let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+                let positions: Vec<Point3> = match reader.read_positions() {
+                    Some(positions) => positions
+                        .map(|[x, y, z]| {
+                            transform.transform_point(Point3::new(x as f64, y as f64, z as f64))
+                        })
+                        .collect(),
+                    None => continue,
+                };
+                let normals: Option<Vec<Vector3>> = reader.read_normals().map(|normals| {
+                    normals
+                        .map(|[x, y, z]| {
+                            normal_transform
+                                .transform_vector(Vector3::new(x as f64, y as f64, z as f64))
+                        })
+                        .collect()
+                });
+                // glTF texture coordinates run down the image, so flip them to run up it
+                let uvs: Option<Vec<(f64, f64)>> = reader.read_tex_coords(0).map(|uvs| {
+                    uvs.into_f32()
+                        .map(|[u, v]| (u as f64, 1. - v as f64))
+                        .collect()
+                });
+                let colors: Option<Vec<Color3>> = reader.read_colors(0).map(|colors| {
+                    colors
+                        .into_rgb_f32()
+                        .map(|[r, g, b]| Color3::new(r as f64, g as f64, b as f64))
+                        .collect()
+                });
+                let indices: Vec<usize> = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+                    None => (0..positions.len()).collect(),
+                };
+                if let Some(&index) = indices.iter().find(|&&i| i >= positions.len()) {
+                    return Err(format!(
+                        "mesh {} refers to vertex {}, but has only {} vertices",
+                        mesh.index(),
+                        index,
+                        positions.len()
+                    ));
+                }
+                let material = self.material(primitive.material().index());
+
+                // Append the primitive's vertices to the shared buffers, offsetting its indices
+                let position_offset = self.mesh.positions.len();
+                let normal_offset = self.mesh.normals.len();
+                let uv_offset = self.mesh.uvs.len();
+                let color_offset = self.mesh.colors.len();
+                let vertex_count = positions.len();
+                self.mesh.positions.extend(positions);
+                let has_normals = extend(&mut self.mesh.normals, normals, vertex_count);
+                let has_uvs = extend(&mut self.mesh.uvs, uvs, vertex_count);
+                let has_colors = extend(&mut self.mesh.colors, colors, vertex_count);
+                let shift =
+                    |[a, b, c]: [usize; 3], offset: usize| [a + offset, b + offset, c + offset];
+                for triangle in indices.chunks_exact(3) {
+                    let triangle = if mirrored {
+                        [triangle[0], triangle[2], triangle[1]]
+                    } else {
+                        [triangle[0], triangle[1], triangle[2]]
+                    };
+                    self.mesh.triangles.push(MeshTriangle {
+                        positions: shift(triangle, position_offset),
+                        normals: has_normals.then(|| shift(triangle, normal_offset)),
+                        uvs: has_uvs.then(|| shift(triangle, uv_offset)),
+                        colors: has_colors.then(|| shift(triangle, color_offset)),
+                        material,
+                    });
+                }
+            }
+        }
+
+        for child in node.children() {
+            self.add_node(&child, transform)?;
+        }
+        Ok(())
+    }
+
+    // Index into the mesh's materials of a glTF material, building it the first time it is used
+    fn material(&mut self, index: Option<usize>) -> usize {
+        if let Some(&material) = self.materials.get(&index) {
+            return material;
+        }
+        let document = self.document;
+        let material: Box<dyn Material> = match index.and_then(|i| document.materials().nth(i)) {
+            Some(material) => {
+                let pbr = material.pbr_metallic_roughness();
+                let [r, g, b, _] = pbr.base_color_factor();
+                let [er, eg, eb] = material.emissive_factor();
+                Box::new(PbrMaterial {
+                    base_color: Color3::new(r as f64, g as f64, b as f64),
+                    base_color_texture: pbr
+                        .base_color_texture()
+                        .map(|info| self.texture(info.texture().source().index(), true)),
+                    metallic: pbr.metallic_factor() as f64,
+                    roughness: pbr.roughness_factor() as f64,
+                    metallic_roughness_texture: pbr
+                        .metallic_roughness_texture()
+                        .map(|info| self.texture(info.texture().source().index(), false)),
+                    emissive: Color3::new(er as f64, eg as f64, eb as f64),
+                    emissive_texture: material
+                        .emissive_texture()
+                        .map(|info| self.texture(info.texture().source().index(), true)),
+                })
+            }
+            None => mesh::default_material(),
+        };
+        self.mesh.materials.push(material);
+        let material = self.mesh.materials.len() - 1;
+        self.materials.insert(index, material);
+        material
+    }
+
+    fn texture(&mut self, image: usize, display: bool) -> Arc<ImageTexture> {
+        let data = &self.images[image];
+        self.textures
+            .entry((image, display))
+            .or_insert_with(|| Arc::new(ImageTexture::new(decode_image(data, display))))
+            .clone()
+    }
+}
+
+// Append one value per vertex to a buffer. Returns false, leaving the buffer unchanged, if the
+// values are missing or there are not enough of them
+fn extend<T>(buffer: &mut Vec<T>, values: Option<Vec<T>>, vertex_count: usize) -> bool {
+    match values {
+        Some(values) if values.len() >= vertex_count => {
+            buffer.extend(values.into_iter().take(vertex_count));
+            true
+        }
+        _ => false,
+    }
+}
+
+// Convert decoded image data to linear color values. Images that store colors hold display
+// values, so the gamma of 2 that the image writers apply is undone, as for PLY vertex colors
+fn decode_image(data: &image::Data, display: bool) -> Canvas {
+    let (channels, bytes) = match data.format {
+        image::Format::R8 => (1, 1),
+        image::Format::R8G8 => (2, 1),
+        image::Format::R8G8B8 => (3, 1),
+        image::Format::R8G8B8A8 => (4, 1),
+        image::Format::R16 => (1, 2),
+        image::Format::R16G16 => (2, 2),
+        image::Format::R16G16B16 => (3, 2),
+        image::Format::R16G16B16A16 => (4, 2),
+        image::Format::R32G32B32FLOAT => (3, 4),
+        image::Format::R32G32B32A32FLOAT => (4, 4),
+    };
+    let value = |b: &[u8]| match bytes {
+        1 => b[0] as f64 / 255.,
+        2 => u16::from_ne_bytes([b[0], b[1]]) as f64 / 65535.,
+        _ => f32::from_ne_bytes([b[0], b[1], b[2], b[3]]) as f64,
+    };
+
+    // An empty image stands for a texture that could not be decoded, so leave it white
+    if data.width == 0 || data.height == 0 {
+        return Canvas::filled(1, 1, Color3::new(1., 1., 1.));
+    }
+    let mut canvas = Canvas::new(data.width, data.height);
+    for (pixel, texel) in canvas
+        .pixels_mut()
+        .iter_mut()
+        .zip(data.pixels.chunks_exact(channels * bytes))
+    {
+        let channel = |i: usize| {
+            let v = value(&texel[i.min(channels - 1) * bytes..]);
+            if display {
+                v * v
+            } else {
+                v
+            }
+        };
+        *pixel = match channels {
+            // Grey images spread their one value to every channel
+            1 | 2 => Color3::new(channel(0), channel(0), channel(0)),
+            _ => Color3::new(channel(0), channel(1), channel(2)),
+        };
+    }
+    canvas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // Write a glTF file with one triangle facing +z, with normals to match, placed by a node with
+    // the given scale
+    fn write_triangle(name: &str, scale: [f64; 3]) -> std::path::PathBuf {
+        let directory = std::env::temp_dir().join(format!("ray_tracer_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let mut buffer = Vec::new();
+        let values: [f32; 18] = [
+            0., 0., 0., 1., 0., 0., 0., 1., 0., 0., 0., 1., 0., 0., 1., 0., 0., 1.,
+        ];
+        for value in values {
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        fs::write(directory.join(format!("{}.bin", name)), &buffer).unwrap();
+        let json = format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "scenes": [{{"nodes": [0]}}],
+                "nodes": [{{"mesh": 0, "scale": {:?}}}],
+                "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0, "NORMAL": 1}}}}]}}],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                      "min": [0, 0, 0], "max": [1, 1, 0]}},
+                    {{"bufferView": 0, "byteOffset": 36, "componentType": 5126, "count": 3,
+                      "type": "VEC3"}}
+                ],
+                "bufferViews": [{{"buffer": 0, "byteLength": 72}}],
+                "buffers": [{{"byteLength": 72, "uri": "{}.bin"}}]
+            }}"#,
+            scale, name
+        );
+        let path = directory.join(format!("{}.gltf", name));
+        fs::write(&path, json).unwrap();
+        path
+    }
+
+    // The normal given by the order of a triangle's vertices, and the normal of its first vertex
+    fn normals(mesh: &MeshData) -> (Vector3, Vector3) {
+        let triangle = &mesh.triangles[0];
+        let [a, b, c] = triangle.positions.map(|i| mesh.positions[i]);
+        let winding = (b - a).cross(c - a).unit();
+        (winding, mesh.normals[triangle.normals.unwrap()[0]].unit())
+    }
+
+    #[test]
+    fn winding_matches_normals() {
+        let mesh = load_mesh(&write_triangle("plain", [2., 1., 1.])).unwrap();
+        let (winding, normal) = normals(&mesh);
+        assert_eq!(winding, Vector3::new(0., 0., 1.));
+        assert_eq!(normal, Vector3::new(0., 0., 1.));
+    }
+
+    #[test]
+    fn mirrored_node_keeps_winding() {
+        let mesh = load_mesh(&write_triangle("mirrored", [-1., 1., 1.])).unwrap();
+        let (winding, normal) = normals(&mesh);
+        assert_eq!(winding, Vector3::new(0., 0., 1.));
+        assert_eq!(normal, Vector3::new(0., 0., 1.));
+        assert_eq!(mesh.positions[1], Point3::new(-1., 0., 0.));
+    }
+}
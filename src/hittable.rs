@@ -1,21 +1,73 @@
+use std::f64::consts::PI;
 use std::ops::Range;
 use std::option::Option;
 use std::vec::Vec;
 
+use crate::aabb::Aabb;
+use crate::bvh::{BuildStats, BvhNode, SahBuilder};
+use crate::instance::Instance;
 use crate::material::Material;
 use crate::ray::Ray;
-use crate::vector::{Point3, Vector3};
+use crate::tlas::Tlas;
+use crate::vector::{Color3, Point3, Vector3};
 
 #[derive(Copy, Clone)]
 pub struct Hit<'a> {
     pub point: Point3,
+    // The normal used for shading, which may be smoothed across the surface
     pub normal: Vector3,
+    // The true normal of the surface at the hit point
+    pub geometric_normal: Vector3,
     pub distance: f64,
-    pub material: &'a Box<dyn Material>,
+    // Surface parameterization, used to look up textures
+    pub uv: (f64, f64),
+    // Weights of each vertex at the hit point, for shapes built from triangles
+    pub barycentric: Option<[f64; 3]>,
+    // Color interpolated from the vertices of meshes that carry one, which tints matte materials
+    pub color: Option<Color3>,
+    pub material: &'a dyn Material,
 }
 
-pub trait Hittable {
-    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit>;
+// Shapes are shared between render threads, so every Hittable must be safe to send and share
+pub trait Hittable: Send + Sync {
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>>;
+
+    // The smallest box containing the whole shape, used to build acceleration structures
+    fn bounding_box(&self) -> Aabb;
+
+    // The stretches of the whole line through the ray, behind its origin as well as ahead of it,
+    // that lie inside the shape, in order along the ray. Only closed shapes have an inside, so
+    // this is only used to combine shapes with CSG.
+    //
+    // By default the line is walked from one hit to the next, with each hit entering the shape
+    // if its geometric normal faces the ray and leaving it otherwise. Shading normals are not
+    // used, as near the silhouette of a smooth mesh they can face the other way
+    fn intervals(&self, ray: &Ray) -> Vec<Interval<'_>> {
+        let mut intervals = Vec::new();
+        let mut entry = None;
+        let mut range = f64::NEG_INFINITY..f64::INFINITY;
+        while let Some(hit) = self.hit(ray, &range) {
+            if hit.geometric_normal.dot(ray.direction) < 0. {
+                entry = entry.or(Some(hit));
+            } else if let Some(enter) = entry.take() {
+                intervals.push(Interval { enter, exit: hit });
+            }
+            // Step just past the hit so that the same surface is not found again
+            range.start = hit.distance + INTERVAL_STEP * (1. + hit.distance.abs());
+        }
+        intervals
+    }
+}
+
+// Relative distance stepped past each hit when walking a ray through a shape
+const INTERVAL_STEP: f64 = 1e-9;
+
+// A stretch of a ray inside a closed shape, from the hit where it enters to the hit where it
+// leaves. The normals of both hits point out of the shape
+#[derive(Copy, Clone)]
+pub struct Interval<'a> {
+    pub enter: Hit<'a>,
+    pub exit: Hit<'a>,
 }
 
 pub struct Sphere {
@@ -24,58 +76,228 @@ pub struct Sphere {
     pub material: Box<dyn Material>,
 }
 
+// Both places where a ray crosses the surface of a sphere, nearest first, or None if it misses
+fn sphere_roots(center: Point3, radius: f64, ray: &Ray) -> Option<(f64, f64)> {
+    // Analytically solve for the intersection between the ray and the surface of the sphere
+    let sphere_to_origin = ray.origin - center;
+    let a = ray.direction.length_squared();
+    let b = ray.direction.dot(sphere_to_origin);
+    let c = sphere_to_origin.length_squared() - radius * radius;
+
+    let discriminant = b * b - a * c;
+    if discriminant <= 0. {
+        // The ray does not intersect the sphere
+        return None;
+    };
+    let dis_sqrt = discriminant.sqrt();
+    Some(((-b - dis_sqrt) / a, (-b + dis_sqrt) / a))
+}
+
+// Choose the intersection point that is closest to the ray origin while still falling in the
+// renderable range
+fn nearest_root((near, far): (f64, f64), range: &Range<f64>) -> Option<f64> {
+    [near, far].iter().copied().find(|t| range.contains(t))
+}
+
+fn sphere_hit<'a>(
+    center: Point3,
+    radius: f64,
+    material: &'a dyn Material,
+    ray: &Ray,
+    t: f64,
+) -> Hit<'a> {
+    let point = ray.at(t);
+    let normal = (point - center) / radius;
+    Hit {
+        point,
+        normal,
+        geometric_normal: normal,
+        distance: t,
+        uv: sphere_uv(normal),
+        barycentric: None,
+        color: None,
+        material,
+    }
+}
+
 impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit> {
-        // Analytically solve for the intersection between the ray and the surface of these sphere
-        let sphere_to_origin = ray.origin - self.center;
-        let a = ray.direction.length_squared();
-        let b = ray.direction.dot(sphere_to_origin);
-        let c = sphere_to_origin.length_squared() - self.radius * self.radius;
-
-        let discriminant = b * b - a * c;
-        if discriminant <= 0. {
-            // The ray does not intersect the sphere
-            return None;
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>> {
+        let t = nearest_root(sphere_roots(self.center, self.radius, ray)?, range)?;
+        Some(sphere_hit(
+            self.center,
+            self.radius,
+            self.material.as_ref(),
+            ray,
+            t,
+        ))
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval<'_>> {
+        // Both roots of the same quadratic as in hit, wherever they are along the ray
+        let (near, far) = match sphere_roots(self.center, self.radius, ray) {
+            Some(roots) => roots,
+            None => return Vec::new(),
         };
-        let dis_sqrt = discriminant.sqrt();
-
-        // Choose the intersection point that is closest to the ray origin while still falling in the renderable range
-        let mut t = (-b - dis_sqrt) / a;
-        if !range.contains(&t) {
-            t = (-b + dis_sqrt) / a;
-            if !range.contains(&t) {
-                return None;
-            }
-        }
-        let point = ray.at(t);
-        let normal = (point - self.center) / self.radius;
-
-        let intersection = Hit {
-            point: point,
-            normal: normal,
-            distance: t,
-            material: &self.material,
+        let material = self.material.as_ref();
+        vec![Interval {
+            enter: sphere_hit(self.center, self.radius, material, ray, near),
+            exit: sphere_hit(self.center, self.radius, material, ray, far),
+        }]
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let extent = Vector3::new(self.radius, self.radius, self.radius);
+        Aabb::new(self.center - extent, self.center + extent)
+    }
+}
+
+// A sphere moving in a straight line at a steady speed, from `start` at time 0 to `end` at time 1.
+// It rests at `start` before then and at `end` after
+pub struct MovingSphere {
+    pub start: Point3,
+    pub end: Point3,
+    pub radius: f64,
+    pub material: Box<dyn Material>,
+}
+
+impl MovingSphere {
+    fn center(&self, time: f64) -> Point3 {
+        self.start + (self.end - self.start) * time.clamp(0., 1.)
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>> {
+        let center = self.center(ray.time);
+        let t = nearest_root(sphere_roots(center, self.radius, ray)?, range)?;
+        Some(sphere_hit(
+            center,
+            self.radius,
+            self.material.as_ref(),
+            ray,
+            t,
+        ))
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval<'_>> {
+        let center = self.center(ray.time);
+        let (near, far) = match sphere_roots(center, self.radius, ray) {
+            Some(roots) => roots,
+            None => return Vec::new(),
         };
-        Some(intersection)
+        let material = self.material.as_ref();
+        vec![Interval {
+            enter: sphere_hit(center, self.radius, material, ray, near),
+            exit: sphere_hit(center, self.radius, material, ray, far),
+        }]
     }
+
+    fn bounding_box(&self) -> Aabb {
+        let extent = Vector3::new(self.radius, self.radius, self.radius);
+        Aabb::new(self.start - extent, self.start + extent)
+            .union(Aabb::new(self.end - extent, self.end + extent))
+    }
+}
+
+// Map a point on the unit sphere to longitude u and latitude v, both from 0 to 1, where v runs
+// from the bottom of the sphere to the top
+fn sphere_uv(point: Point3) -> (f64, f64) {
+    let theta = (-point.y()).clamp(-1., 1.).acos();
+    let phi = (-point.z()).atan2(point.x()) + PI;
+    (phi / (2. * PI), theta / PI)
 }
 
 pub struct World {
     shapes: Vec<Box<dyn Hittable>>,
+    // Instances get a hierarchy of their own, which can be rebuilt alone when they move
+    instances: Tlas,
+    bbox: Aabb,
 }
 
 impl World {
     pub fn new() -> Self {
-        Self { shapes: Vec::new() }
+        Self {
+            shapes: Vec::new(),
+            instances: Tlas::default(),
+            bbox: Aabb::empty(),
+        }
     }
 
     pub fn add(&mut self, elem: Box<dyn Hittable>) {
+        self.bbox = self.bbox.union(elem.bounding_box());
         self.shapes.push(elem);
     }
+
+    // Add an instance to the top-level hierarchy, where it can be moved later. Instances of
+    // unbounded shapes cannot go in a hierarchy, so they are added as ordinary shapes
+    pub fn add_instance(&mut self, instance: Instance) {
+        if !instance.bounding_box().is_bounded() {
+            self.add(Box::new(instance));
+            return;
+        }
+        self.bbox = self.bbox.union(instance.bounding_box());
+        self.instances.push(instance);
+    }
+
+    pub fn instances(&self) -> &[Instance] {
+        self.instances.instances()
+    }
+
+    // Instances can be moved through this. They are tested one by one until rebuild_instances
+    // is called
+    pub fn instances_mut(&mut self) -> &mut [Instance] {
+        self.instances.instances_mut()
+    }
+
+    // Rebuild only the top-level hierarchy over the instances, leaving the other shapes and the
+    // geometry the instances share untouched
+    pub fn rebuild_instances(&mut self) {
+        self.instances.rebuild();
+        self.bbox = self
+            .shapes
+            .iter()
+            .fold(self.instances.bounding_box(), |bbox, shape| {
+                bbox.union(shape.bounding_box())
+            });
+    }
+
+    // Replace the flat list of shapes with a bounding volume hierarchy so rays are tested against
+    // a logarithmic number of shapes instead of all of them
+    pub fn build_bvh(&mut self) {
+        let shapes = self.take_bounded_shapes();
+        self.shapes.push(BvhNode::build(shapes));
+        self.instances.rebuild();
+    }
+
+    // Like build_bvh, but places split planes using the surface area heuristic, which produces
+    // trees that are considerably cheaper to traverse at the cost of a slower build
+    pub fn build_sah_bvh(&mut self, builder: &SahBuilder) -> BuildStats {
+        let shapes = self.take_bounded_shapes();
+        let (root, stats) = builder.build(shapes);
+        self.shapes.push(root);
+        self.instances.rebuild();
+        stats
+    }
+
+    // Remove and return the shapes that can go in a hierarchy. Unbounded shapes such as planes
+    // stay in the list and are tested against every ray
+    fn take_bounded_shapes(&mut self) -> Vec<Box<dyn Hittable>> {
+        let (bounded, unbounded) = std::mem::take(&mut self.shapes)
+            .into_iter()
+            .partition(|shape| shape.bounding_box().is_bounded());
+        self.shapes = unbounded;
+        bounded
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Hittable for World {
-    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit> {
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>> {
         // For all objects in the world, return the valid hit that is closes to the camera
         let mut closest_hit: Option<Hit> = None;
 
@@ -86,6 +308,16 @@ impl Hittable for World {
                 }
             }
         }
-        closest_hit
+
+        // Instances only matter if they are closer than the closest of the other shapes
+        let instance_range = Range {
+            start: range.start,
+            end: closest_hit.map_or(range.end, |hit| hit.distance),
+        };
+        self.instances.hit(ray, &instance_range).or(closest_hit)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
     }
 }
@@ -0,0 +1,255 @@
+use std::{
+    fs,
+    io::{self, BufRead},
+    path::Path,
+};
+
+use crate::{canvas::Canvas, vector::Color3};
+
+// Read a linear high dynamic range image, choosing the format from the file extension
+pub fn read_image(path: &Path) -> io::Result<Canvas> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    let mut stream = io::BufReader::new(fs::File::open(path)?);
+    match extension.as_deref() {
+        Some("hdr") => read_hdr(&mut stream),
+        Some("pfm") => read_pfm(&mut stream),
+        _ => Err(invalid_data(format!(
+            "unsupported image format for {}, expected .hdr or .pfm",
+            path.display()
+        ))),
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Largest image that will be read, enough for a 16384 by 8192 environment map. Images are
+// allocated from the size in their header before any pixels are read, so a corrupt header could
+// otherwise ask for more memory than there is
+const MAX_PIXELS: usize = 1 << 27;
+
+// Allocate an image of the size given by a header, if it is not too large
+fn new_canvas(width: u32, height: u32) -> io::Result<Canvas> {
+    match (width as usize).checked_mul(height as usize) {
+        Some(pixels) if pixels <= MAX_PIXELS => Ok(Canvas::new(width, height)),
+        _ => Err(invalid_data(format!(
+            "image of {} by {} pixels is too large",
+            width, height
+        ))),
+    }
+}
+
+fn read_line(stream: &mut dyn BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    if stream.read_line(&mut line)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(line.trim_end().to_string())
+}
+
+// Read a Radiance RGBE image, as written by most HDR capture and paint tools
+pub fn read_hdr(stream: &mut dyn BufRead) -> io::Result<Canvas> {
+    let magic = read_line(stream)?;
+    if magic != "#?RADIANCE" && magic != "#?RGBE" {
+        return Err(invalid_data("not a Radiance HDR image".into()));
+    }
+    loop {
+        let line = read_line(stream)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid_data(format!(
+                    "unsupported HDR pixel format {}",
+                    format
+                )));
+            }
+        }
+    }
+
+    // Only the standard orientation, with rows stored top to bottom and left to right, is supported
+    let resolution = read_line(stream)?;
+    let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (height.parse::<u32>(), width.parse::<u32>()),
+        _ => {
+            return Err(invalid_data(format!(
+                "unsupported HDR resolution line {}",
+                resolution
+            )))
+        }
+    };
+    let (height, width) = match (height, width) {
+        (Ok(height), Ok(width)) => (height, width),
+        _ => {
+            return Err(invalid_data(format!(
+                "invalid HDR resolution line {}",
+                resolution
+            )))
+        }
+    };
+
+    let mut canvas = new_canvas(width, height)?;
+    let mut scanline = vec![[0u8; 4]; width as usize];
+    for row in canvas.rows_mut() {
+        read_hdr_scanline(stream, &mut scanline)?;
+        for (pixel, rgbe) in row.iter_mut().zip(&scanline) {
+            *pixel = if rgbe[3] == 0 {
+                Color3::new(0., 0., 0.)
+            } else {
+                let scale = 2f64.powi(rgbe[3] as i32 - 136);
+                Color3::new(
+                    rgbe[0] as f64 * scale,
+                    rgbe[1] as f64 * scale,
+                    rgbe[2] as f64 * scale,
+                )
+            };
+        }
+    }
+    Ok(canvas)
+}
+
+fn read_hdr_scanline(stream: &mut dyn BufRead, scanline: &mut [[u8; 4]]) -> io::Result<()> {
+    let mut first = [0u8; 4];
+    stream.read_exact(&mut first)?;
+    let width = scanline.len();
+    let run_length_encoded = (8..0x8000).contains(&width) && first[0] == 2 && first[1] == 2;
+    if !run_length_encoded {
+        // Uncompressed scanline
+        scanline[0] = first;
+        for pixel in scanline[1..].iter_mut() {
+            stream.read_exact(pixel)?;
+        }
+        return Ok(());
+    }
+    if ((first[2] as usize) << 8 | first[3] as usize) != width {
+        return Err(invalid_data(
+            "HDR scanline width does not match image".into(),
+        ));
+    }
+
+    // Each of the four components is stored separately as a sequence of runs and literals
+    for component in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0u8; 1];
+            stream.read_exact(&mut count)?;
+            let (count, is_run) = if count[0] > 128 {
+                (count[0] as usize - 128, true)
+            } else {
+                (count[0] as usize, false)
+            };
+            if count == 0 || x + count > width {
+                return Err(invalid_data("corrupt HDR scanline".into()));
+            }
+            if is_run {
+                let mut value = [0u8; 1];
+                stream.read_exact(&mut value)?;
+                for pixel in &mut scanline[x..x + count] {
+                    pixel[component] = value[0];
+                }
+            } else {
+                let mut values = vec![0u8; count];
+                stream.read_exact(&mut values)?;
+                for (pixel, value) in scanline[x..x + count].iter_mut().zip(values) {
+                    pixel[component] = value;
+                }
+            }
+            x += count;
+        }
+    }
+    Ok(())
+}
+
+// Read a color or greyscale portable float map
+pub fn read_pfm(stream: &mut dyn BufRead) -> io::Result<Canvas> {
+    let channels = match read_line(stream)?.as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid_data("not a portable float map".into())),
+    };
+    let dimensions = read_line(stream)?;
+    let (width, height) = match dimensions.split_whitespace().collect::<Vec<_>>()[..] {
+        [width, height] => match (width.parse::<u32>(), height.parse::<u32>()) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => {
+                return Err(invalid_data(format!(
+                    "invalid PFM dimensions {}",
+                    dimensions
+                )))
+            }
+        },
+        _ => {
+            return Err(invalid_data(format!(
+                "invalid PFM dimensions {}",
+                dimensions
+            )))
+        }
+    };
+    let scale = read_line(stream)?;
+    // The sign of the scale gives the byte order of the data
+    let little_endian = match scale.parse::<f64>() {
+        Ok(scale) => scale < 0.,
+        Err(_) => return Err(invalid_data(format!("invalid PFM scale {}", scale))),
+    };
+
+    let mut canvas = new_canvas(width, height)?;
+    let mut line = vec![0u8; width as usize * channels * 4];
+    // Scanlines are stored from the bottom of the image to the top
+    for j in (0..height).rev() {
+        stream.read_exact(&mut line)?;
+        let values: Vec<f64> = line
+            .chunks_exact(4)
+            .map(|bytes| {
+                let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+                if little_endian {
+                    f32::from_le_bytes(bytes) as f64
+                } else {
+                    f32::from_be_bytes(bytes) as f64
+                }
+            })
+            .collect();
+        for (pixel, value) in canvas
+            .row_mut(j)
+            .iter_mut()
+            .zip(values.chunks_exact(channels))
+        {
+            *pixel = match value {
+                [r, g, b] => Color3::new(*r, *g, *b),
+                _ => Color3::new(value[0], value[0], value[0]),
+            };
+        }
+    }
+    Ok(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_small_pfm() {
+        let mut data = b"Pf\n2 1\n-1.0\n".to_vec();
+        for value in [0.25f32, 2.] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        let canvas = read_pfm(&mut &data[..]).unwrap();
+        assert_eq!((canvas.width, canvas.height), (2, 1));
+        assert_eq!(*canvas.get_pixel(1, 0), Color3::new(2., 2., 2.));
+    }
+
+    #[test]
+    fn reject_huge_images() {
+        let pfm = b"PF\n65535 65535\n-1.0\n";
+        let err = read_pfm(&mut &pfm[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let hdr = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 65535 +X 65535\n";
+        let err = read_hdr(&mut &hdr[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,274 @@
+use std::borrow::Cow;
+use std::ops::Range;
+use std::option::Option;
+use std::sync::Arc;
+use std::vec::Vec;
+
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable, Interval};
+use crate::matrix::Matrix4;
+use crate::ray::Ray;
+use crate::vector::{Point3, Vector3};
+
+// A shape placed in the scene by an affine transform. The shape is shared rather than copied, so
+// one mesh can be placed many times at the cost of one matrix each. The transform can instead be
+// animated by keyframes, and each ray then sees the shape where it is at the ray's time
+pub struct Instance {
+    shape: Arc<dyn Hittable>,
+    // Used unless the instance is animated
+    transforms: Transforms,
+    // Sorted by time, and empty unless the instance is animated
+    keyframes: Vec<Keyframe>,
+    bbox: Aabb,
+}
+
+// Where an animated instance is at one moment: scaled, then rotated by degrees about the x, y and z
+// axes in that order, then translated. Between keyframes each of these is interpolated linearly,
+// and before the first or after the last the instance stays where that keyframe puts it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Keyframe {
+    pub time: f64,
+    pub scale: Vector3,
+    pub rotation: Vector3,
+    pub translate: Vector3,
+}
+
+impl Keyframe {
+    pub fn transform(&self) -> Matrix4 {
+        Matrix4::translation(self.translate)
+            * Matrix4::euler_rotation(self.rotation)
+            * Matrix4::scale(self.scale)
+    }
+
+    fn lerp(&self, other: &Keyframe, t: f64) -> Keyframe {
+        Keyframe {
+            time: self.time + (other.time - self.time) * t,
+            scale: self.scale + (other.scale - self.scale) * t,
+            rotation: self.rotation + (other.rotation - self.rotation) * t,
+            translate: self.translate + (other.translate - self.translate) * t,
+        }
+    }
+
+    // The inverse is built from the parts in reverse rather than by inverting the whole matrix,
+    // as it is needed again for every ray
+    fn transforms(&self) -> Transforms {
+        let inverse = Matrix4::scale(Vector3::new(
+            1. / self.scale.x(),
+            1. / self.scale.y(),
+            1. / self.scale.z(),
+        )) * Matrix4::euler_rotation(self.rotation).transpose()
+            * Matrix4::translation(-self.translate);
+        Transforms {
+            transform: self.transform(),
+            inverse,
+            normal_transform: inverse.transpose(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Transforms {
+    // From the shape's own space to the scene, and back
+    transform: Matrix4,
+    inverse: Matrix4,
+    // Carries normals out of the shape's space. The inverse transpose keeps them perpendicular
+    // to the surface under non-uniform scaling
+    normal_transform: Matrix4,
+}
+
+impl Transforms {
+    fn new(transform: Matrix4) -> Option<Self> {
+        let inverse = transform.inverse()?;
+        Some(Self {
+            transform,
+            inverse,
+            normal_transform: inverse.transpose(),
+        })
+    }
+
+    // Express a ray in the shape's own space. The direction is left unnormalized, so distances
+    // along the ray are the same in both spaces
+    fn to_local(&self, ray: &Ray) -> Ray {
+        Ray {
+            origin: self.inverse.transform_point(ray.origin),
+            direction: self.inverse.transform_vector(ray.direction),
+            time: ray.time,
+        }
+    }
+
+    fn to_world<'a>(&self, hit: Hit<'a>) -> Hit<'a> {
+        Hit {
+            point: self.transform.transform_point(hit.point),
+            normal: self.normal_transform.transform_vector(hit.normal).unit(),
+            geometric_normal: self
+                .normal_transform
+                .transform_vector(hit.geometric_normal)
+                .unit(),
+            ..hit
+        }
+    }
+}
+
+// Bound the corners of a box after they are transformed
+fn transformed_box(bbox: Aabb, transform: &Matrix4) -> Aabb {
+    let mut transformed = Aabb::empty();
+    for corner in 0..8 {
+        let pick = |axis: usize| {
+            if corner & (1 << axis) == 0 {
+                bbox.min.axis(axis)
+            } else {
+                bbox.max.axis(axis)
+            }
+        };
+        let point = transform.transform_point(Point3::new(pick(0), pick(1), pick(2)));
+        transformed = transformed.union(Aabb::new(point, point));
+    }
+    transformed
+}
+
+impl Instance {
+    // Place a shape with a transform, which must not be singular, returning None if it is
+    pub fn new(shape: Arc<dyn Hittable>, transform: Matrix4) -> Option<Self> {
+        let mut instance = Self {
+            shape,
+            transforms: Transforms::new(Matrix4::identity())?,
+            keyframes: Vec::new(),
+            bbox: Aabb::empty(),
+        };
+        if instance.set_transform(transform) {
+            Some(instance)
+        } else {
+            None
+        }
+    }
+
+    // Place a shape that moves through keyframes, returning None if there are none or they could
+    // make the transform singular
+    pub fn animated(shape: Arc<dyn Hittable>, keyframes: Vec<Keyframe>) -> Option<Self> {
+        let mut instance = Self::new(shape, Matrix4::identity())?;
+        if instance.set_keyframes(keyframes) {
+            Some(instance)
+        } else {
+            None
+        }
+    }
+
+    pub fn shape(&self) -> &Arc<dyn Hittable> {
+        &self.shape
+    }
+
+    // The transform from the shape's own space to the scene at a given time
+    pub fn transform_at(&self, time: f64) -> Matrix4 {
+        self.transforms_at(time).transform
+    }
+
+    // Move the instance. Returns false, leaving the instance where it was, if the transform is
+    // singular. Any hierarchy the instance is in must be rebuilt afterwards
+    pub fn set_transform(&mut self, transform: Matrix4) -> bool {
+        let transforms = match Transforms::new(transform) {
+            Some(transforms) => transforms,
+            None => return false,
+        };
+        let local = self.shape.bounding_box();
+        self.bbox = if local.is_bounded() {
+            transformed_box(local, &transform)
+        } else {
+            local
+        };
+        self.transforms = transforms;
+        self.keyframes.clear();
+        true
+    }
+
+    // Animate the instance. The scale along each axis must not be zero or change sign between
+    // keyframes, as the shape would be flattened on the way. Returns false, leaving the instance
+    // as it was, if it does or there are no keyframes. Any hierarchy the instance is in must be
+    // rebuilt afterwards
+    pub fn set_keyframes(&mut self, mut keyframes: Vec<Keyframe>) -> bool {
+        let first = match keyframes.first() {
+            Some(first) => first.scale,
+            None => return false,
+        };
+        let flattened = keyframes
+            .iter()
+            .any(|keyframe| (0..3).any(|axis| keyframe.scale.axis(axis) * first.axis(axis) <= 0.));
+        if flattened {
+            return false;
+        }
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        let local = self.shape.bounding_box();
+        self.bbox = if local.is_bounded() {
+            keyframes.windows(2).fold(
+                transformed_box(local, &keyframes[0].transform()),
+                |bbox, pair| bbox.union(segment_box(local, &pair[0], &pair[1])),
+            )
+        } else {
+            local
+        };
+        self.keyframes = keyframes;
+        true
+    }
+
+    fn transforms_at(&self, time: f64) -> Cow<'_, Transforms> {
+        let keyframes = &self.keyframes;
+        if keyframes.is_empty() {
+            return Cow::Borrowed(&self.transforms);
+        }
+        let next = keyframes.partition_point(|keyframe| keyframe.time <= time);
+        let keyframe = if next == 0 {
+            keyframes[0]
+        } else if next == keyframes.len() {
+            keyframes[next - 1]
+        } else {
+            let (a, b) = (&keyframes[next - 1], &keyframes[next]);
+            a.lerp(b, (time - a.time) / (b.time - a.time))
+        };
+        Cow::Owned(keyframe.transforms())
+    }
+}
+
+// Bound a bounded shape over the motion between two keyframes
+fn segment_box(local: Aabb, a: &Keyframe, b: &Keyframe) -> Aabb {
+    if a.rotation == b.rotation {
+        // Without rotation every point moves in a straight line, so it stays within the boxes at
+        // either end
+        return transformed_box(local, &a.transform())
+            .union(transformed_box(local, &b.transform()));
+    }
+    // Rotation can swing points outside those boxes, but never further from the translation than
+    // the farthest point of the shape at the largest scale
+    let center = local.centroid();
+    let reach = center.length() + (local.max - center).length();
+    let sphere_box = |keyframe: &Keyframe| {
+        let scale = keyframe.scale;
+        let radius = reach * scale.x().abs().max(scale.y().abs()).max(scale.z().abs());
+        let extent = Vector3::new(radius, radius, radius);
+        Aabb::new(keyframe.translate - extent, keyframe.translate + extent)
+    };
+    sphere_box(a).union(sphere_box(b))
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>> {
+        let transforms = self.transforms_at(ray.time);
+        let hit = self.shape.hit(&transforms.to_local(ray), range)?;
+        Some(transforms.to_world(hit))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval<'_>> {
+        let transforms = self.transforms_at(ray.time);
+        self.shape
+            .intervals(&transforms.to_local(ray))
+            .into_iter()
+            .map(|interval| Interval {
+                enter: transforms.to_world(interval.enter),
+                exit: transforms.to_world(interval.exit),
+            })
+            .collect()
+    }
+}
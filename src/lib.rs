@@ -0,0 +1,29 @@
+pub mod aabb;
+pub mod background;
+pub mod bvh;
+pub mod canvas;
+pub mod csg;
+pub mod cuboid;
+pub mod gltf;
+pub mod hittable;
+pub mod input;
+pub mod instance;
+pub mod material;
+pub mod matrix;
+pub mod medium;
+pub mod mesh;
+pub mod obj;
+pub mod output;
+pub mod planar;
+pub mod ply;
+pub mod polynomial;
+pub mod quadric;
+pub mod ray;
+pub mod render;
+pub mod scene;
+pub mod stl;
+pub mod texture;
+pub mod tlas;
+pub mod torus;
+pub mod triangle;
+pub mod vector;
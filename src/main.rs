@@ -1,18 +1,22 @@
-mod hittable;
-mod material;
-mod ray;
-mod render;
-mod vector;
-use material::{DialectricMaterial, LambertianMaterial, Material, MirrorMaterial};
-use rand::{rngs::ThreadRng, Rng};
-use render::{Camera, Canvas};
-use vector::Color3;
-
-use crate::{
+use clap::{Parser, ValueEnum};
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+use ray_tracer::{
+    bvh::SahBuilder,
     hittable::{Sphere, World},
-    vector::{write_color, Point3},
+    material::{DialectricMaterial, LambertianMaterial, Material, MirrorMaterial},
+    output::{self, BitDepth, ExrPrecision, PngOptions},
+    planar::Plane,
+    render::{CameraSettings, RenderSettings},
+    scene::{self, Scene},
+    vector::{Color3, Point3, Vector3},
+};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    iter::Iterator,
+    path::PathBuf,
+    process,
 };
-use std::{io, iter::Iterator};
 
 const ASPECT_RATIO: f64 = 16. / 9.;
 const IMAGE_HEIGHT: u32 = 800;
@@ -23,17 +27,172 @@ const PIXEL_SAMPLES: usize = 100;
 const FOCUS_DISTANCE: f64 = 10.; // Controls distance of virtual lens from focus plane
 const DEFOCUS_ANGLE: f64 = 0.6; // Controls size of virtual lens
 
-fn write_image(stream: &mut dyn io::Write, canvas: &Canvas) -> io::Result<()> {
-    stream.write_all(format!("P3\n{} {}\n255\n", canvas.width, canvas.height).as_bytes())?;
-    for j in 0..canvas.height {
-        for i in 0..canvas.width {
-            write_color(stream, &canvas.get_pixel(i, j))?;
+/// Render a scene file, or the built-in random scene, to an image
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// TOML scene file, or glTF file, to render. The built-in random scene is rendered if omitted
+    scene: Option<PathBuf>,
+
+    /// File to write the image to. The image is written to stdout if omitted or "-"
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Image format. Inferred from the output file extension if omitted
+    #[arg(short, long, value_enum)]
+    format: Option<OutputFormat>,
+
+    /// Bits per channel for PNG output
+    #[arg(long, value_enum, default_value_t = PngBitDepth::Eight)]
+    bit_depth: PngBitDepth,
+
+    /// Include an alpha channel in PNG output
+    #[arg(long)]
+    alpha: bool,
+
+    /// Precision of the channels in OpenEXR output
+    #[arg(long, value_enum, default_value_t = ExrChannelPrecision::Float)]
+    exr_precision: ExrChannelPrecision,
+
+    /// Image width in pixels. Changes the aspect ratio if the height is also given
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    width: Option<u32>,
+
+    /// Image height in pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    height: Option<u32>,
+
+    /// Number of rays sampled per pixel
+    #[arg(short, long, value_parser = clap::value_parser!(u64).range(1..))]
+    samples: Option<u64>,
+
+    /// Number of times a ray may bounce before it is considered absorbed
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_depth: Option<u64>,
+
+    /// Seed for scene generation and rendering. A random seed is used if omitted
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Number of render threads. Defaults to one per available core
+    #[arg(short = 'j', long, value_parser = clap::value_parser!(u64).range(1..))]
+    threads: Option<u64>,
+
+    /// Vertical field of view in degrees
+    #[arg(long, value_parser = parse_fov)]
+    fov: Option<f64>,
+
+    /// Camera position, as X,Y,Z
+    #[arg(long, value_parser = parse_point, allow_hyphen_values = true)]
+    look_from: Option<Point3>,
+
+    /// Point the camera looks at, as X,Y,Z
+    #[arg(long, value_parser = parse_point, allow_hyphen_values = true)]
+    look_at: Option<Point3>,
+
+    /// Angle of the cone of rays through each pixel, which controls the amount of defocus blur
+    #[arg(long, value_parser = parse_non_negative)]
+    defocus_angle: Option<f64>,
+
+    /// Distance from the camera to the plane that is in perfect focus
+    #[arg(long, value_parser = parse_positive)]
+    focus_distance: Option<f64>,
+
+    /// Times the shutter opens and closes, as OPEN,CLOSE. Moving objects are blurred over this
+    /// interval
+    #[arg(long, value_parser = parse_shutter, allow_hyphen_values = true)]
+    shutter: Option<(f64, f64)>,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum OutputFormat {
+    /// Plain text portable pixmap
+    Ppm,
+    /// Portable network graphics
+    Png,
+    /// OpenEXR, storing linear unclamped radiance
+    Exr,
+    /// Portable float map, storing linear unclamped radiance
+    Pfm,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum PngBitDepth {
+    #[value(name = "8")]
+    Eight,
+    #[value(name = "16")]
+    Sixteen,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum ExrChannelPrecision {
+    /// 16-bit floating point
+    Half,
+    /// 32-bit floating point
+    Float,
+}
+
+impl OutputFormat {
+    fn from_extension(path: &std::path::Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "ppm" => Some(OutputFormat::Ppm),
+            "png" => Some(OutputFormat::Png),
+            "exr" => Some(OutputFormat::Exr),
+            "pfm" => Some(OutputFormat::Pfm),
+            _ => None,
+        }
+    }
+}
+
+fn parse_point(s: &str) -> Result<Point3, String> {
+    let coordinates = s
+        .split(',')
+        .map(|c| c.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| err.to_string())?;
+    match coordinates[..] {
+        [x, y, z] => Ok(Point3::new(x, y, z)),
+        _ => Err("expected three comma-separated coordinates X,Y,Z".into()),
+    }
+}
+
+fn parse_positive(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>().map_err(|err| err.to_string())? {
+        x if x > 0. && x.is_finite() => Ok(x),
+        _ => Err("must be a positive number".into()),
+    }
+}
+
+fn parse_non_negative(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>().map_err(|err| err.to_string())? {
+        x if x >= 0. && x.is_finite() => Ok(x),
+        _ => Err("must not be negative".into()),
+    }
+}
+
+fn parse_fov(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>().map_err(|err| err.to_string())? {
+        x if x > 0. && x < 180. => Ok(x),
+        _ => Err("must be between 0 and 180 degrees".into()),
+    }
+}
+
+fn parse_shutter(s: &str) -> Result<(f64, f64), String> {
+    let times = s
+        .split(',')
+        .map(|t| t.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| err.to_string())?;
+    match times[..] {
+        [open, close] if open <= close && open.is_finite() && close.is_finite() => {
+            Ok((open, close))
         }
+        [_, _] => Err("the shutter must not close before it opens".into()),
+        _ => Err("expected two comma-separated times OPEN,CLOSE".into()),
     }
-    Ok(())
 }
 
-fn random_material(rng: &mut ThreadRng) -> Box<dyn Material> {
+fn random_material(rng: &mut dyn RngCore) -> Box<dyn Material> {
     let x = rng.gen_range(0. ..1.);
     if x < 0.6 {
         let albedo = Color3::random(rng) * Color3::random(rng);
@@ -51,28 +210,17 @@ fn random_material(rng: &mut ThreadRng) -> Box<dyn Material> {
     }
 }
 
-fn main() -> io::Result<()> {
-    let camera = Camera::new(
-        ASPECT_RATIO,
-        IMAGE_HEIGHT,
-        VERTICAL_FOV,
-        Point3::new(13., 3., 0.),
-        Point3::new(0., 1., 0.),
-        DEFOCUS_ANGLE,
-        FOCUS_DISTANCE,
-        PIXEL_SAMPLES,
-    );
-
-    let mut rng = rand::thread_rng();
-    let mut stream = io::stdout();
+// The default scene: a large mirrored sphere surrounded by randomly placed smaller spheres
+fn random_scene(settings: RenderSettings) -> Scene {
+    let mut rng = StdRng::seed_from_u64(settings.seed);
     let mut world = World::new();
 
     let ground_material = Box::new(LambertianMaterial {
         albedo: Color3::new(0.8, 0.8, 0.),
     });
-    let ground = Sphere {
-        center: Point3::new(0., -1000., -1.),
-        radius: 1000.,
+    let ground = Plane {
+        point: Point3::new(0., 0., 0.),
+        normal: Vector3::new(0., 1., 0.),
         material: (ground_material as Box<dyn Material>),
     };
     world.add(Box::new(ground));
@@ -106,7 +254,7 @@ fn main() -> io::Result<()> {
             let sphere = Sphere {
                 center,
                 radius,
-                material: material,
+                material,
             };
             world.add(Box::new(sphere));
         }
@@ -122,12 +270,157 @@ fn main() -> io::Result<()> {
         let sphere = Sphere {
             center,
             radius: rng.gen_range(0.1..0.5),
-            material: material,
+            material,
         };
         world.add(Box::new(sphere));
     }
 
-    let canvas = camera.draw(&world, &mut rng);
-    write_image(&mut stream, &canvas)?;
-    Ok(())
+    Scene {
+        world,
+        camera: CameraSettings {
+            aspect_ratio: ASPECT_RATIO,
+            image_height: IMAGE_HEIGHT,
+            image_width: None,
+            vertical_fov: VERTICAL_FOV,
+            look_from: Point3::new(13., 3., 0.),
+            look_at: Point3::new(0., 1., 0.),
+            up: Vector3::new(0., 1., 0.),
+            defocus_angle: DEFOCUS_ANGLE,
+            focus_distance: FOCUS_DISTANCE,
+            shutter_open: 0.,
+            shutter_close: 0.,
+            samples: PIXEL_SAMPLES,
+        },
+        render: settings,
+    }
+}
+
+fn main() -> io::Result<()> {
+    let args = Args::parse();
+
+    let format = match (args.format, &args.output) {
+        (Some(format), _) => format,
+        (None, Some(path)) if path.as_os_str() != "-" => match OutputFormat::from_extension(path) {
+            Some(format) => format,
+            None => fail(&format!(
+                "cannot infer image format from {}, use --format",
+                path.display()
+            )),
+        },
+        (None, _) => OutputFormat::Ppm,
+    };
+
+    let mut scene = match &args.scene {
+        Some(path) => match scene::load(path) {
+            Ok(scene) => scene,
+            Err(err) => fail(&format!("{}: {}", path.display(), err)),
+        },
+        None => {
+            let mut settings = RenderSettings::default();
+            if let Some(seed) = args.seed {
+                settings.seed = seed;
+            }
+            random_scene(settings)
+        }
+    };
+    apply_overrides(&args, &mut scene);
+    if scene.camera.look_from == scene.camera.look_at {
+        fail("the camera position and look-at point must be different");
+    }
+
+    // Report the seed so that any render can be reproduced exactly
+    eprintln!("seed: {}", scene.render.seed);
+
+    let stats = scene.world.build_sah_bvh(&SahBuilder::default());
+    eprintln!("{}", stats);
+    if !scene.world.instances().is_empty() {
+        eprintln!(
+            "built top-level hierarchy over {} instances",
+            scene.world.instances().len()
+        );
+    }
+
+    let canvas = scene.camera.camera().draw(&scene.world, &scene.render);
+    let mut stream: Box<dyn Write> = match &args.output {
+        Some(path) if path.as_os_str() != "-" => Box::new(BufWriter::new(File::create(path)?)),
+        _ => Box::new(BufWriter::new(io::stdout())),
+    };
+    match format {
+        OutputFormat::Ppm => output::write_ppm(&mut stream, &canvas)?,
+        OutputFormat::Png => {
+            let options = PngOptions {
+                bit_depth: match args.bit_depth {
+                    PngBitDepth::Eight => BitDepth::Eight,
+                    PngBitDepth::Sixteen => BitDepth::Sixteen,
+                },
+                alpha: args.alpha,
+            };
+            output::write_png(&mut stream, &canvas, &options)?
+        }
+        OutputFormat::Exr => {
+            let precision = match args.exr_precision {
+                ExrChannelPrecision::Half => ExrPrecision::Half,
+                ExrChannelPrecision::Float => ExrPrecision::Float,
+            };
+            output::write_exr(&mut stream, &canvas, precision)?
+        }
+        OutputFormat::Pfm => output::write_pfm(&mut stream, &canvas)?,
+    }
+    stream.flush()
+}
+
+// Replace any scene settings that were given on the command line
+fn apply_overrides(args: &Args, scene: &mut Scene) {
+    let camera = &mut scene.camera;
+    match (args.width, args.height) {
+        (Some(width), Some(height)) => {
+            camera.aspect_ratio = width as f64 / height as f64;
+            camera.image_height = height;
+            camera.image_width = Some(width);
+        }
+        (Some(width), None) => {
+            camera.image_height = ((width as f64 / camera.aspect_ratio).round() as u32).max(1);
+            camera.image_width = Some(width);
+        }
+        (None, Some(height)) => camera.image_height = height,
+        (None, None) => (),
+    }
+    if let Some(samples) = args.samples {
+        camera.samples = samples as usize;
+    }
+    if let Some(fov) = args.fov {
+        camera.vertical_fov = fov;
+    }
+    if let Some(look_from) = args.look_from {
+        camera.look_from = look_from;
+    }
+    if let Some(look_at) = args.look_at {
+        camera.look_at = look_at;
+    }
+    if let Some(defocus_angle) = args.defocus_angle {
+        camera.defocus_angle = defocus_angle;
+    }
+    if let Some(focus_distance) = args.focus_distance {
+        camera.focus_distance = focus_distance;
+    }
+    if let Some((open, close)) = args.shutter {
+        camera.shutter_open = open;
+        camera.shutter_close = close;
+    }
+
+    let render = &mut scene.render;
+    if let Some(seed) = args.seed {
+        render.seed = seed;
+    }
+    if let Some(threads) = args.threads {
+        render.threads = threads as usize;
+    }
+    if let Some(max_depth) = args.max_depth {
+        render.max_depth = max_depth as usize;
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(1);
 }
@@ -1,9 +1,11 @@
-use rand::rngs::ThreadRng;
-use rand::Rng;
+use rand::{Rng, RngCore};
+use std::{f64::consts::PI, sync::Arc};
 
 use crate::{
     hittable::Hit,
+    medium::Medium,
     ray::Ray,
+    texture::ImageTexture,
     vector::{Color3, Vector3},
 };
 
@@ -18,8 +20,35 @@ impl ScatteredHit {
     }
 }
 
-pub trait Material {
-    fn scatter(&self, ray: &Ray, hit: &Hit, rng: &mut ThreadRng) -> Option<ScatteredHit>;
+// The light a surface reflects toward the viewer from a given direction, and the probability
+// density with which `scatter` would have chosen that direction
+pub struct ScatterEvaluation {
+    // The BSDF multiplied by the cosine of the angle between the direction and the normal
+    pub value: Color3,
+    pub pdf: f64,
+}
+
+// Materials are shared between render threads along with the shapes that own them
+pub trait Material: Send + Sync {
+    fn scatter(&self, ray: &Ray, hit: &Hit, rng: &mut dyn RngCore) -> Option<ScatteredHit>;
+
+    // Evaluate scattering of light arriving from `direction` toward the origin of `ray`. This lets
+    // the renderer sample light sources directly, which is only possible for materials that
+    // scatter over a range of directions, so perfectly specular materials return None
+    fn evaluate(&self, _ray: &Ray, _hit: &Hit, _direction: Vector3) -> Option<ScatterEvaluation> {
+        None
+    }
+
+    // Light given off by the surface itself. Most materials only reflect light, so emit nothing
+    fn emitted(&self, _hit: &Hit) -> Color3 {
+        Color3::new(0., 0., 0.)
+    }
+
+    // The medium filling the inside of a shape whose surface is only its boundary, or None for
+    // ordinary surfaces
+    fn medium(&self) -> Option<&Medium> {
+        None
+    }
 }
 
 // Lambert or "matte" material bounces light in a random direction
@@ -27,8 +56,17 @@ pub struct LambertianMaterial {
     pub albedo: Color3,
 }
 
+impl LambertianMaterial {
+    fn albedo(&self, hit: &Hit) -> Color3 {
+        match hit.color {
+            Some(color) => self.albedo * color,
+            None => self.albedo,
+        }
+    }
+}
+
 impl Material for LambertianMaterial {
-    fn scatter(&self, _ray: &Ray, hit: &Hit, rng: &mut ThreadRng) -> Option<ScatteredHit> {
+    fn scatter(&self, ray: &Ray, hit: &Hit, rng: &mut dyn RngCore) -> Option<ScatteredHit> {
         let bounce_direction = hit.normal + Vector3::rand_unit(rng);
         let bounce_ray = Ray::new(
             hit.point,
@@ -37,8 +75,19 @@ impl Material for LambertianMaterial {
             } else {
                 bounce_direction
             },
+            ray.time,
         );
-        Some(ScatteredHit::new(bounce_ray, self.albedo))
+        Some(ScatteredHit::new(bounce_ray, self.albedo(hit)))
+    }
+
+    fn evaluate(&self, _ray: &Ray, hit: &Hit, direction: Vector3) -> Option<ScatterEvaluation> {
+        // Offsetting the normal by a random unit vector samples directions in proportion to the
+        // cosine of their angle with the normal
+        let cos_theta = hit.normal.dot(direction.unit()).max(0.);
+        Some(ScatterEvaluation {
+            value: self.albedo(hit) * (cos_theta / PI),
+            pdf: cos_theta / PI,
+        })
     }
 }
 
@@ -48,11 +97,11 @@ pub struct MirrorMaterial {
 }
 
 impl Material for MirrorMaterial {
-    fn scatter(&self, ray: &Ray, hit: &Hit, rng: &mut ThreadRng) -> Option<ScatteredHit> {
+    fn scatter(&self, ray: &Ray, hit: &Hit, rng: &mut dyn RngCore) -> Option<ScatteredHit> {
         let reflected = ray.direction.reflect(hit.normal);
         let bounce_direction = reflected + Vector3::rand_unit(rng) * self.fuzziness;
         if bounce_direction.dot(hit.normal) > 0. {
-            let bounce_ray = Ray::new(hit.point, bounce_direction);
+            let bounce_ray = Ray::new(hit.point, bounce_direction, ray.time);
             Some(ScatteredHit::new(bounce_ray, self.albedo))
         } else {
             None
@@ -71,7 +120,7 @@ fn reflectance(cos_theta: f64, refraction_index: f64) -> f64 {
 }
 
 impl Material for DialectricMaterial {
-    fn scatter(&self, ray: &Ray, hit: &Hit, rng: &mut ThreadRng) -> Option<ScatteredHit> {
+    fn scatter(&self, ray: &Ray, hit: &Hit, rng: &mut dyn RngCore) -> Option<ScatteredHit> {
         let (refraction_ratio, normal) = if ray.direction.dot(hit.normal) < 0. {
             // hitting front face
             (1. / self.refractive_index, hit.normal)
@@ -96,8 +145,114 @@ impl Material for DialectricMaterial {
         };
 
         Some(ScatteredHit::new(
-            Ray::new(hit.point, bounce_direction),
+            Ray::new(hit.point, bounce_direction, ray.time),
             Color3::new(1., 1., 1.),
         ))
     }
 }
+
+// A light source that emits the same light in every direction and reflects nothing
+pub struct DiffuseLight {
+    pub emit: Color3,
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _ray: &Ray, _hit: &Hit, _rng: &mut dyn RngCore) -> Option<ScatteredHit> {
+        None
+    }
+
+    fn emitted(&self, _hit: &Hit) -> Color3 {
+        self.emit
+    }
+}
+
+// The metallic-roughness model used by glTF. A surface is a blend of a matte base and a glossy
+// coat: metals tint their reflections with the base color and have no matte part, while other
+// materials reflect about 4% of light untinted and scatter the rest diffusely
+pub struct PbrMaterial {
+    pub base_color: Color3,
+    pub base_color_texture: Option<Arc<ImageTexture>>,
+    pub metallic: f64,
+    pub roughness: f64,
+    // Roughness in the green channel and metalness in the blue, scaling the factors above
+    pub metallic_roughness_texture: Option<Arc<ImageTexture>>,
+    pub emissive: Color3,
+    pub emissive_texture: Option<Arc<ImageTexture>>,
+}
+
+// Reflectance of non-metals seen head on
+const DIELECTRIC_REFLECTANCE: f64 = 0.04;
+
+impl PbrMaterial {
+    fn base_color(&self, hit: &Hit) -> Color3 {
+        let mut color = self.base_color;
+        if let Some(texture) = &self.base_color_texture {
+            color *= texture.sample(hit.uv);
+        }
+        if let Some(vertex_color) = hit.color {
+            color *= vertex_color;
+        }
+        color
+    }
+
+    fn metallic_roughness(&self, hit: &Hit) -> (f64, f64) {
+        match &self.metallic_roughness_texture {
+            Some(texture) => {
+                let sample = texture.sample(hit.uv);
+                (self.metallic * sample.z(), self.roughness * sample.y())
+            }
+            None => (self.metallic, self.roughness),
+        }
+    }
+}
+
+impl Material for PbrMaterial {
+    fn scatter(&self, ray: &Ray, hit: &Hit, rng: &mut dyn RngCore) -> Option<ScatteredHit> {
+        let base_color = self.base_color(hit);
+        let (metallic, roughness) = self.metallic_roughness(hit);
+        let (metallic, roughness) = (metallic.clamp(0., 1.), roughness.clamp(0., 1.));
+
+        // Choose the glossy coat with the probability that it reflects light, so that the matte
+        // base, weighted by what remains, keeps exactly the base color
+        let specular = Color3::new(
+            DIELECTRIC_REFLECTANCE,
+            DIELECTRIC_REFLECTANCE,
+            DIELECTRIC_REFLECTANCE,
+        ) * (1. - metallic)
+            + base_color * metallic;
+        let specular_probability = DIELECTRIC_REFLECTANCE * (1. - metallic) + metallic;
+        if rng.gen_range(0. ..1.) < specular_probability {
+            let reflected = ray.direction.unit().reflect(hit.normal);
+            let bounce_direction = reflected + Vector3::rand_unit(rng) * (roughness * roughness);
+            if bounce_direction.dot(hit.normal) <= 0. {
+                return None;
+            }
+            Some(ScatteredHit::new(
+                Ray::new(hit.point, bounce_direction, ray.time),
+                specular / specular_probability,
+            ))
+        } else {
+            let bounce_direction = hit.normal + Vector3::rand_unit(rng);
+            let bounce_ray = Ray::new(
+                hit.point,
+                if bounce_direction.near_zero() {
+                    hit.normal
+                } else {
+                    bounce_direction
+                },
+                ray.time,
+            );
+            Some(ScatteredHit::new(bounce_ray, base_color))
+        }
+    }
+
+    // The glossy coat has no closed form density to weigh against light sampling, so light
+    // sources are only found by scattering and `evaluate` keeps its default
+
+    fn emitted(&self, hit: &Hit) -> Color3 {
+        match &self.emissive_texture {
+            Some(texture) => self.emissive * texture.sample(hit.uv),
+            None => self.emissive,
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::ops::Mul;
+
+use crate::vector::{Point3, Vector3};
+
+// A 4x4 matrix for affine transformations of points and directions, stored by rows. Points are
+// treated as column vectors, so `a * b` applies `b` first and then `a`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Matrix4(pub [[f64; 4]; 4]);
+
+impl Matrix4 {
+    pub fn identity() -> Self {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.;
+        }
+        Matrix4(m)
+    }
+
+    // Build a matrix from its columns, the layout used by glTF and most graphics APIs
+    pub fn from_columns(columns: [[f64; 4]; 4]) -> Self {
+        Matrix4(columns).transpose()
+    }
+
+    pub fn translation(offset: Vector3) -> Self {
+        let mut m = Matrix4::identity();
+        for axis in 0..3 {
+            m.0[axis][3] = offset.axis(axis);
+        }
+        m
+    }
+
+    // Scale by a separate factor along each axis
+    pub fn scale(factors: Vector3) -> Self {
+        let mut m = Matrix4::identity();
+        for axis in 0..3 {
+            m.0[axis][axis] = factors.axis(axis);
+        }
+        m
+    }
+
+    // A right-handed rotation by the given number of degrees about the x, y or z axis
+    pub fn rotation(axis: usize, degrees: f64) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+        let mut m = Matrix4::identity();
+        m.0[a][a] = cos;
+        m.0[a][b] = -sin;
+        m.0[b][a] = sin;
+        m.0[b][b] = cos;
+        m
+    }
+
+    // Rotation by degrees about the x, y and z axes, in that order
+    pub fn euler_rotation(degrees: Vector3) -> Self {
+        Matrix4::rotation(2, degrees.z())
+            * Matrix4::rotation(1, degrees.y())
+            * Matrix4::rotation(0, degrees.x())
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.0[j][i];
+            }
+        }
+        Matrix4(m)
+    }
+
+    pub fn transform_point(&self, p: Point3) -> Point3 {
+        let m = &self.0;
+        Point3::new(
+            m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3],
+            m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3],
+            m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3],
+        )
+    }
+
+    // Transform a direction, which is unaffected by translation
+    pub fn transform_vector(&self, v: Vector3) -> Vector3 {
+        let m = &self.0;
+        Vector3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+
+    // Determinant of the upper 3x3 block, which is how the transform scales volumes. It is
+    // negative for transforms that mirror space, turning shapes inside out
+    pub fn linear_determinant(&self) -> f64 {
+        let m = &self.0;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    // The inverse of the matrix, or None if it is singular. Uses Gauss-Jordan elimination with
+    // partial pivoting
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.0;
+        let mut inverse = Matrix4::identity().0;
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))
+                .unwrap_or(column);
+            if a[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = 1. / a[column][column];
+            for j in 0..4 {
+                a[column][j] *= scale;
+                inverse[column][j] *= scale;
+            }
+            for row in 0..4 {
+                if row != column {
+                    let factor = a[row][column];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[column][j];
+                        inverse[row][j] -= factor * inverse[column][j];
+                    }
+                }
+            }
+        }
+        Some(Matrix4(inverse))
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.0[i][k] * other.0[k][j]).sum();
+            }
+        }
+        Matrix4(m)
+    }
+}
@@ -0,0 +1,113 @@
+use rand::{Rng, RngCore};
+use std::f64::consts::PI;
+
+use crate::{
+    hittable::Hit,
+    material::{Material, ScatterEvaluation, ScatteredHit},
+    planar::tangent_basis,
+    ray::Ray,
+    vector::{Color3, Vector3},
+};
+
+// How a particle in a medium redirects the light that strikes it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PhaseFunction {
+    // Every direction equally
+    Isotropic,
+    // Mostly forward for an asymmetry `g` between 0 and 1, as in fog and clouds, or mostly back
+    // toward the light for negative `g`
+    HenyeyGreenstein { g: f64 },
+}
+
+impl PhaseFunction {
+    // Density over directions for a turn with the given cosine between the incoming and outgoing
+    // directions
+    fn pdf(self, cos_theta: f64) -> f64 {
+        match self {
+            PhaseFunction::Isotropic => 1. / (4. * PI),
+            PhaseFunction::HenyeyGreenstein { g } => {
+                let denominator = 1. + g * g - 2. * g * cos_theta;
+                (1. - g * g) / (4. * PI * denominator * denominator.sqrt())
+            }
+        }
+    }
+
+    // Choose the cosine of the turn in proportion to the density
+    fn sample_cos_theta(self, rng: &mut dyn RngCore) -> f64 {
+        let xi = rng.gen_range(0. ..1.);
+        match self {
+            PhaseFunction::HenyeyGreenstein { g } if g.abs() > 1e-3 => {
+                let s = (1. - g * g) / (1. - g + 2. * g * xi);
+                ((1. + g * g - s * s) / (2. * g)).clamp(-1., 1.)
+            }
+            _ => 1. - 2. * xi,
+        }
+    }
+}
+
+// A homogeneous participating medium, such as fog, smoke or a milky liquid. Given to a closed shape
+// as its material, it fills the inside of the shape: the surface itself is invisible, and light
+// crossing the medium is scattered at random distances by particles with the given `density`,
+// keeping `albedo` of its energy at each scattering. The renderer samples those distances when
+// it finds a ray is travelling through a medium, and the medium then acts as the material at the
+// point where the ray scatters
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Medium {
+    pub density: f64,
+    pub albedo: Color3,
+    pub phase: PhaseFunction,
+}
+
+// A medium filling all the space between a scene's shapes. Were it endless, every ray escaping
+// the scene would scatter in it sooner or later and the background could never be seen, so it
+// only reaches `distance` from wherever a ray sets out. A ray that gets that far without meeting
+// a surface or scattering leaves the fog and picks up the light of the background
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Fog {
+    pub medium: Medium,
+    pub distance: f64,
+}
+
+impl Medium {
+    // Distance a ray travels through the medium before it scatters, which is exponentially
+    // distributed with the density as its rate
+    pub fn sample_distance(&self, rng: &mut dyn RngCore) -> f64 {
+        let xi: f64 = rng.gen_range(0. ..1.);
+        -(1. - xi).ln() / self.density
+    }
+
+    // Fraction of light that crosses the given distance through the medium without scattering
+    pub fn transmittance(&self, distance: f64) -> f64 {
+        (-self.density * distance).exp()
+    }
+}
+
+impl Material for Medium {
+    fn scatter(&self, ray: &Ray, hit: &Hit, rng: &mut dyn RngCore) -> Option<ScatteredHit> {
+        let forward = ray.direction.unit();
+        let cos_theta = self.phase.sample_cos_theta(rng);
+        let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+        let phi = rng.gen_range(0. ..2. * PI);
+        let (tangent, bitangent) = tangent_basis(forward);
+        let direction =
+            forward * cos_theta + (tangent * phi.cos() + bitangent * phi.sin()) * sin_theta;
+        Some(ScatteredHit::new(
+            Ray::new(hit.point, direction, ray.time),
+            self.albedo,
+        ))
+    }
+
+    fn evaluate(&self, ray: &Ray, _hit: &Hit, direction: Vector3) -> Option<ScatterEvaluation> {
+        let pdf = self
+            .phase
+            .pdf(ray.direction.unit().dot(direction.unit()).clamp(-1., 1.));
+        Some(ScatterEvaluation {
+            value: self.albedo * pdf,
+            pdf,
+        })
+    }
+
+    fn medium(&self) -> Option<&Medium> {
+        Some(self)
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    fmt, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    aabb::Aabb,
+    bvh::SahBuilder,
+    gltf,
+    hittable::{Hit, Hittable},
+    material::{LambertianMaterial, Material},
+    obj, ply,
+    ray::Ray,
+    stl,
+    triangle::{intersect_triangle, triangle_hit},
+    vector::{Color3, Point3, Vector3},
+};
+
+// A triangle of a mesh, referring to its vertex data by index into the mesh's shared buffers
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MeshTriangle {
+    pub positions: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+    pub colors: Option<[usize; 3]>,
+    pub material: usize,
+}
+
+// The geometry of a triangle mesh. Vertices are stored once and shared by every triangle that
+// uses them
+#[derive(Default)]
+pub struct MeshData {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vector3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub colors: Vec<Color3>,
+    pub triangles: Vec<MeshTriangle>,
+    pub materials: Vec<Box<dyn Material>>,
+    // Named ranges of triangles, in the order they appear in the source file
+    pub groups: Vec<(String, Range<usize>)>,
+}
+
+impl MeshData {
+    // Use a single material for the whole mesh, replacing any it was loaded with
+    pub fn set_material(&mut self, material: Box<dyn Material>) {
+        self.materials = vec![material];
+        for triangle in &mut self.triangles {
+            triangle.material = 0;
+        }
+    }
+
+    fn vertices(&self, triangle: &MeshTriangle) -> [Point3; 3] {
+        let [a, b, c] = triangle.positions;
+        [self.positions[a], self.positions[b], self.positions[c]]
+    }
+}
+
+// Load a mesh, choosing the format from the file extension
+pub fn load(path: &Path) -> Result<MeshData, MeshError> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("obj") => obj::load(path),
+        Some("ply") => ply::load(path),
+        Some("stl") => stl::load(path),
+        Some("gltf") | Some("glb") => gltf::load_mesh(path),
+        _ => Err(MeshError::Invalid {
+            file: path.to_path_buf(),
+            message: "unsupported mesh format, expected .obj, .ply, .stl, .gltf or .glb".into(),
+        }),
+    }
+}
+
+// The plain grey given to meshes whose files do not describe their materials
+pub fn default_material() -> Box<dyn Material> {
+    Box::new(LambertianMaterial {
+        albedo: Color3::new(0.8, 0.8, 0.8),
+    })
+}
+
+// A triangle mesh that can be placed in a World. The triangles are organized in their own
+// bounding volume hierarchy, so the mesh behaves like a single shape
+pub struct Mesh {
+    data: Arc<MeshData>,
+    bvh: Box<dyn Hittable>,
+}
+
+impl Mesh {
+    pub fn new(data: MeshData) -> Self {
+        let data = Arc::new(data);
+        let faces: Vec<Box<dyn Hittable>> = (0..data.triangles.len())
+            .map(|index| {
+                Box::new(MeshFace {
+                    data: data.clone(),
+                    index,
+                }) as Box<dyn Hittable>
+            })
+            .collect();
+        let (bvh, _) = SahBuilder::default().build(faces);
+        Self { data, bvh }
+    }
+
+    pub fn data(&self) -> &MeshData {
+        &self.data
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>> {
+        self.bvh.hit(ray, range)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounding_box()
+    }
+}
+
+// A single triangle of a mesh, as seen by the mesh's bounding volume hierarchy
+struct MeshFace {
+    data: Arc<MeshData>,
+    index: usize,
+}
+
+impl Hittable for MeshFace {
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>> {
+        let triangle = &self.data.triangles[self.index];
+        let vertices = self.data.vertices(triangle);
+        let coordinates = intersect_triangle(ray, &vertices, range)?;
+        let normals = triangle.normals.map(|[a, b, c]| {
+            [
+                self.data.normals[a],
+                self.data.normals[b],
+                self.data.normals[c],
+            ]
+        });
+        let uvs = triangle
+            .uvs
+            .map(|[a, b, c]| [self.data.uvs[a], self.data.uvs[b], self.data.uvs[c]]);
+        let mut hit = triangle_hit(
+            ray,
+            &vertices,
+            normals.as_ref(),
+            uvs.as_ref(),
+            coordinates,
+            self.data.materials[triangle.material].as_ref(),
+        );
+        if let (Some([a, b, c]), Some(weights)) = (triangle.colors, hit.barycentric) {
+            hit.color = Some(
+                self.data.colors[a] * weights[0]
+                    + self.data.colors[b] * weights[1]
+                    + self.data.colors[c] * weights[2],
+            );
+        }
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let [a, b, c] = self.data.vertices(&self.data.triangles[self.index]);
+        Aabb::new(a.min(b).min(c), a.max(b).max(c))
+    }
+}
+
+#[derive(Debug)]
+pub enum MeshError {
+    Io(io::Error),
+    // A line of a text file could not be understood
+    Syntax {
+        file: PathBuf,
+        line: usize,
+        message: String,
+    },
+    // The file does not describe a valid mesh, such as a binary file that is cut short or
+    // refers to vertices it does not contain
+    Invalid {
+        file: PathBuf,
+        message: String,
+    },
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshError::Io(err) => write!(f, "{}", err),
+            MeshError::Syntax {
+                file,
+                line,
+                message,
+            } => write!(f, "{}: line {}: {}", file.display(), line, message),
+            MeshError::Invalid { file, message } => write!(f, "{}: {}", file.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+impl From<io::Error> for MeshError {
+    fn from(err: io::Error) -> Self {
+        MeshError::Io(err)
+    }
+}
@@ -0,0 +1,335 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::{
+    material::{DialectricMaterial, DiffuseLight, LambertianMaterial, Material, MirrorMaterial},
+    mesh::{self, MeshData, MeshError, MeshTriangle},
+    vector::{Color3, Point3, Vector3},
+};
+
+// Load a Wavefront OBJ model along with any MTL material libraries it refers to. Polygons are
+// split into triangles around their first vertex, so they are expected to be convex
+pub fn load(path: &Path) -> Result<MeshData, MeshError> {
+    let source = fs::read_to_string(path)?;
+    parse(&source, path)
+}
+
+// Parse the contents of an OBJ file. `path` is used in error messages and to find material
+// libraries, which are looked up relative to it
+pub fn parse(source: &str, path: &Path) -> Result<MeshData, MeshError> {
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut mesh = MeshData::default();
+    let mut library: HashMap<String, MtlMaterial> = HashMap::new();
+    // Index into the mesh's materials of each material name used so far
+    let mut used_materials: HashMap<String, usize> = HashMap::new();
+    let mut material = None;
+    let mut group = None;
+
+    for (number, line) in source.lines().enumerate() {
+        let syntax = |message: String| MeshError::Syntax {
+            file: path.to_path_buf(),
+            line: number + 1,
+            message,
+        };
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue,
+        };
+        let arguments: Vec<&str> = words.collect();
+
+        match keyword {
+            "v" => {
+                // Any extra values, such as a w coordinate or vertex colors, are ignored
+                let [x, y, z] = numbers::<3>(&arguments, 3).map_err(syntax)?;
+                mesh.positions.push(Point3::new(x, y, z));
+            }
+            "vn" => {
+                let [x, y, z] = numbers::<3>(&arguments, 3).map_err(syntax)?;
+                mesh.normals.push(Vector3::new(x, y, z));
+            }
+            "vt" => {
+                let [u, v] = numbers::<2>(&arguments, 1).map_err(syntax)?;
+                mesh.uvs.push((u, v));
+            }
+            "f" => {
+                if arguments.len() < 3 {
+                    return Err(syntax("a face needs at least three vertices".into()));
+                }
+                let corners = arguments
+                    .iter()
+                    .map(|corner| face_vertex(corner, &mesh))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(syntax)?;
+                let material = match material {
+                    Some(index) => index,
+                    None => {
+                        // Faces before any usemtl statement get a plain grey material
+                        let index = mesh.materials.len();
+                        mesh.materials.push(mesh::default_material());
+                        material = Some(index);
+                        index
+                    }
+                };
+                for i in 1..corners.len() - 1 {
+                    let [a, b, c] = [corners[0], corners[i], corners[i + 1]];
+                    mesh.triangles.push(MeshTriangle {
+                        positions: [a.0, b.0, c.0],
+                        uvs: match (a.1, b.1, c.1) {
+                            (Some(a), Some(b), Some(c)) => Some([a, b, c]),
+                            _ => None,
+                        },
+                        normals: match (a.2, b.2, c.2) {
+                            (Some(a), Some(b), Some(c)) => Some([a, b, c]),
+                            _ => None,
+                        },
+                        colors: None,
+                        material,
+                    });
+                }
+            }
+            "g" | "o" => {
+                end_group(&mut mesh, group.take());
+                group = Some((arguments.join(" "), mesh.triangles.len()));
+            }
+            "mtllib" => {
+                for name in &arguments {
+                    let mtl_path = directory.join(name);
+                    let materials = load_mtl(&mtl_path).map_err(|err| match err {
+                        MeshError::Io(err) => syntax(format!(
+                            "cannot read material library {}: {}",
+                            mtl_path.display(),
+                            err
+                        )),
+                        err => err,
+                    })?;
+                    library.extend(materials);
+                }
+            }
+            "usemtl" => {
+                let name = arguments.join(" ");
+                material = Some(match used_materials.get(&name) {
+                    Some(index) => *index,
+                    None => {
+                        let desc = library
+                            .get(&name)
+                            .ok_or_else(|| syntax(format!("unknown material '{}'", name)))?;
+                        let index = mesh.materials.len();
+                        mesh.materials.push(desc.build());
+                        used_materials.insert(name, index);
+                        index
+                    }
+                });
+            }
+            // Smoothing groups, lines, points, curves and surfaces are not rendered
+            _ => {}
+        }
+    }
+    end_group(&mut mesh, group);
+    Ok(mesh)
+}
+
+fn end_group(mesh: &mut MeshData, group: Option<(String, usize)>) {
+    if let Some((name, start)) = group {
+        if start < mesh.triangles.len() {
+            mesh.groups.push((name, start..mesh.triangles.len()));
+        }
+    }
+}
+
+// Parse at least `required` and at most N numbers, leaving any that are missing at zero
+fn numbers<const N: usize>(arguments: &[&str], required: usize) -> Result<[f64; N], String> {
+    if arguments.len() < required {
+        return Err(format!("expected {} numbers", required));
+    }
+    let mut values = [0.; N];
+    for (value, argument) in values.iter_mut().zip(arguments) {
+        *value = argument
+            .parse()
+            .map_err(|_| format!("invalid number '{}'", argument))?;
+    }
+    Ok(values)
+}
+
+// Parse a face corner of the form v, v/vt, v//vn or v/vt/vn into indices into the mesh buffers
+fn face_vertex(
+    corner: &str,
+    mesh: &MeshData,
+) -> Result<(usize, Option<usize>, Option<usize>), String> {
+    let mut parts = corner.split('/');
+    let position = match parts.next() {
+        Some(index) => resolve_index(index, mesh.positions.len())?,
+        None => return Err(format!("invalid face vertex '{}'", corner)),
+    };
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(index) => Some(resolve_index(index, mesh.uvs.len())?),
+    };
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(index) => Some(resolve_index(index, mesh.normals.len())?),
+    };
+    if parts.next().is_some() {
+        return Err(format!("invalid face vertex '{}'", corner));
+    }
+    Ok((position, uv, normal))
+}
+
+// OBJ indices count from 1, or backward from the most recent element when negative
+fn resolve_index(index: &str, count: usize) -> Result<usize, String> {
+    let value: i64 = index
+        .parse()
+        .map_err(|_| format!("invalid index '{}'", index))?;
+    let resolved = if value > 0 {
+        value - 1
+    } else {
+        count as i64 + value
+    };
+    if value == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!(
+            "index {} is out of range, there are {} elements",
+            value, count
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+// The parts of an MTL material that can be represented by the renderer's materials
+struct MtlMaterial {
+    diffuse: Color3,
+    specular: Color3,
+    emissive: Color3,
+    shininess: f64,
+    refractive_index: Option<f64>,
+    opacity: f64,
+    illumination: Option<u32>,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self {
+            diffuse: Color3::new(0.8, 0.8, 0.8),
+            specular: Color3::new(0., 0., 0.),
+            emissive: Color3::new(0., 0., 0.),
+            shininess: 0.,
+            refractive_index: None,
+            opacity: 1.,
+            illumination: None,
+        }
+    }
+}
+
+impl MtlMaterial {
+    // Pick the closest of the renderer's materials. Emission wins over everything else, then
+    // transparency, then whichever of the specular and diffuse colors is brighter
+    fn build(&self) -> Box<dyn Material> {
+        let transparent = self.opacity < 1. || matches!(self.illumination, Some(4 | 6 | 7 | 9));
+        if !self.emissive.near_zero() {
+            Box::new(DiffuseLight {
+                emit: self.emissive,
+            })
+        } else if transparent {
+            Box::new(DialectricMaterial {
+                refractive_index: self.refractive_index.unwrap_or(1.5),
+            })
+        } else if max_component(self.specular) > max_component(self.diffuse) {
+            // Map the Phong exponent onto an approximate roughness
+            Box::new(MirrorMaterial {
+                albedo: self.specular,
+                fuzziness: (2. / (self.shininess.max(0.) + 2.)).sqrt(),
+            })
+        } else {
+            Box::new(LambertianMaterial {
+                albedo: self.diffuse,
+            })
+        }
+    }
+}
+
+fn max_component(color: Color3) -> f64 {
+    color.x().max(color.y()).max(color.z())
+}
+
+fn load_mtl(path: &Path) -> Result<HashMap<String, MtlMaterial>, MeshError> {
+    let source = fs::read_to_string(path)?;
+    parse_mtl(&source, path)
+}
+
+fn parse_mtl(source: &str, path: &Path) -> Result<HashMap<String, MtlMaterial>, MeshError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (number, line) in source.lines().enumerate() {
+        let syntax = |message: String| MeshError::Syntax {
+            file: path.to_path_buf(),
+            line: number + 1,
+            message,
+        };
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue,
+        };
+        let arguments: Vec<&str> = words.collect();
+
+        if keyword == "newmtl" {
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material);
+            }
+            current = Some((arguments.join(" "), MtlMaterial::default()));
+            continue;
+        }
+        let material = match &mut current {
+            Some((_, material)) => material,
+            None if matches!(
+                keyword,
+                "Kd" | "Ks" | "Ke" | "Ns" | "Ni" | "d" | "Tr" | "illum"
+            ) =>
+            {
+                return Err(syntax(format!("{} before any newmtl statement", keyword)))
+            }
+            None => continue,
+        };
+        match keyword {
+            "Kd" => material.diffuse = mtl_color(&arguments).map_err(syntax)?,
+            "Ks" => material.specular = mtl_color(&arguments).map_err(syntax)?,
+            "Ke" => material.emissive = mtl_color(&arguments).map_err(syntax)?,
+            "Ns" => material.shininess = numbers::<1>(&arguments, 1).map_err(syntax)?[0],
+            "Ni" => {
+                let index = numbers::<1>(&arguments, 1).map_err(syntax)?[0];
+                if !(index > 0. && index.is_finite()) {
+                    return Err(syntax("refractive index must be positive".into()));
+                }
+                material.refractive_index = Some(index);
+            }
+            "d" => material.opacity = numbers::<1>(&arguments, 1).map_err(syntax)?[0],
+            "Tr" => material.opacity = 1. - numbers::<1>(&arguments, 1).map_err(syntax)?[0],
+            "illum" => {
+                material.illumination = Some(
+                    arguments
+                        .first()
+                        .and_then(|value| value.parse().ok())
+                        .ok_or_else(|| syntax("invalid illumination model".into()))?,
+                )
+            }
+            // Texture maps and other properties have no equivalent yet
+            _ => {}
+        }
+    }
+    if let Some((name, material)) = current {
+        materials.insert(name, material);
+    }
+    Ok(materials)
+}
+
+// A color given as r g b, or as a single value for grey
+fn mtl_color(arguments: &[&str]) -> Result<Color3, String> {
+    if matches!(arguments.first(), Some(&"spectral") | Some(&"xyz")) {
+        return Err("only RGB colors are supported".into());
+    }
+    let [r, g, b] = numbers::<3>(arguments, 1)?;
+    Ok(if arguments.len() < 3 {
+        Color3::new(r, r, r)
+    } else {
+        Color3::new(r, g, b)
+    })
+}
@@ -0,0 +1,234 @@
+use std::io;
+
+use crate::{canvas::Canvas, vector::write_color};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BitDepth {
+    Eight,
+    Sixteen,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PngOptions {
+    pub bit_depth: BitDepth,
+    // Add a fully opaque alpha channel, for tools that expect RGBA images
+    pub alpha: bool,
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        Self {
+            bit_depth: BitDepth::Eight,
+            alpha: false,
+        }
+    }
+}
+
+// Write the canvas as a plain text portable pixmap
+pub fn write_ppm(stream: &mut dyn io::Write, canvas: &Canvas) -> io::Result<()> {
+    stream.write_all(format!("P3\n{} {}\n255\n", canvas.width, canvas.height).as_bytes())?;
+    for (_, _, color) in canvas.iter() {
+        write_color(stream, color)?;
+    }
+    Ok(())
+}
+
+pub fn write_png(
+    stream: &mut dyn io::Write,
+    canvas: &Canvas,
+    options: &PngOptions,
+) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(stream, canvas.width, canvas.height);
+    encoder.set_color(if options.alpha {
+        png::ColorType::Rgba
+    } else {
+        png::ColorType::Rgb
+    });
+    encoder.set_depth(match options.bit_depth {
+        BitDepth::Eight => png::BitDepth::Eight,
+        BitDepth::Sixteen => png::BitDepth::Sixteen,
+    });
+
+    let channels = if options.alpha { 4 } else { 3 };
+    let bytes_per_channel = match options.bit_depth {
+        BitDepth::Eight => 1,
+        BitDepth::Sixteen => 2,
+    };
+    let mut data =
+        Vec::with_capacity((canvas.width * canvas.height) as usize * channels * bytes_per_channel);
+    for color in canvas.pixels() {
+        let pixel = [color.x(), color.y(), color.z(), 1.];
+        for &value in &pixel[..channels] {
+            match options.bit_depth {
+                BitDepth::Eight => data.push(quantize(value, 256) as u8),
+                // PNG stores 16 bit samples in big-endian order
+                BitDepth::Sixteen => {
+                    data.extend_from_slice(&(quantize(value, 65536) as u16).to_be_bytes())
+                }
+            }
+        }
+    }
+
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(&data).map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
+
+// Gamma correct a linear channel value and map it to one of `levels` integer values, in the same
+// way as the PPM writer
+fn quantize(value: f64, levels: u32) -> u32 {
+    let corrected = value.max(0.).sqrt();
+    ((corrected * levels as f64) as u32).min(levels - 1)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExrPrecision {
+    Half,
+    Float,
+}
+
+// Write the canvas as a linear, unclamped, uncompressed scanline OpenEXR image
+pub fn write_exr(
+    stream: &mut dyn io::Write,
+    canvas: &Canvas,
+    precision: ExrPrecision,
+) -> io::Result<()> {
+    let (pixel_type, bytes_per_channel) = match precision {
+        ExrPrecision::Half => (1i32, 2),
+        ExrPrecision::Float => (2, 4),
+    };
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]); // magic number
+    header.extend_from_slice(&2u32.to_le_bytes()); // version 2, single part scanline image
+
+    // Channels must be listed in alphabetical order, and the pixel data of each scanline follows
+    // the same order
+    let mut channels = Vec::new();
+    for name in ["B", "G", "R"] {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&pixel_type.to_le_bytes());
+        channels.extend_from_slice(&[0, 0, 0, 0]); // linear flag and reserved bytes
+        channels.extend_from_slice(&1i32.to_le_bytes()); // x sampling
+        channels.extend_from_slice(&1i32.to_le_bytes()); // y sampling
+    }
+    channels.push(0);
+    exr_attribute(&mut header, "channels", "chlist", &channels);
+    exr_attribute(&mut header, "compression", "compression", &[0]);
+
+    let mut window = Vec::new();
+    for value in [0, 0, canvas.width as i32 - 1, canvas.height as i32 - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+    exr_attribute(&mut header, "dataWindow", "box2i", &window);
+    exr_attribute(&mut header, "displayWindow", "box2i", &window);
+    exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]); // increasing y
+    exr_attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    exr_attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+    stream.write_all(&header)?;
+
+    // Uncompressed images store one scanline per block, located through a table of file offsets
+    let line_size = canvas.width as usize * 3 * bytes_per_channel;
+    let block_size = 8 + line_size;
+    let first_block = header.len() + canvas.height as usize * 8;
+    for j in 0..canvas.height as usize {
+        stream.write_all(&((first_block + j * block_size) as u64).to_le_bytes())?;
+    }
+
+    let mut line = Vec::with_capacity(block_size);
+    for j in 0..canvas.height {
+        line.clear();
+        line.extend_from_slice(&(j as i32).to_le_bytes());
+        line.extend_from_slice(&(line_size as i32).to_le_bytes());
+        for channel in [2, 1, 0] {
+            for color in canvas.row(j) {
+                let value = color.axis(channel) as f32;
+                match precision {
+                    ExrPrecision::Half => line.extend_from_slice(&to_half(value).to_le_bytes()),
+                    ExrPrecision::Float => line.extend_from_slice(&value.to_le_bytes()),
+                }
+            }
+        }
+        stream.write_all(&line)?;
+    }
+    Ok(())
+}
+
+fn exr_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+// Convert to an IEEE 754 half precision float, rounding to nearest even
+fn to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        // Infinity stays infinity and NaN stays NaN
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        // Too large to represent
+        return sign | 0x7c00;
+    }
+    if half_exponent <= 0 {
+        // Subnormal half, or too small and flushed to zero
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - half_exponent) as u32;
+        let half_mantissa = mantissa >> shift;
+        let remainder = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round_up = remainder > halfway || (remainder == halfway && half_mantissa & 1 == 1);
+        return sign | (half_mantissa + round_up as u32) as u16;
+    }
+
+    let half = ((half_exponent as u32) << 10) | (mantissa >> 13);
+    let remainder = mantissa & 0x1fff;
+    let round_up = remainder > 0x1000 || (remainder == 0x1000 && half & 1 == 1);
+    // Rounding may carry into the exponent, which correctly produces the next power of two or
+    // infinity
+    sign | (half + round_up as u32) as u16
+}
+
+// Write the canvas as a linear, unclamped portable float map
+pub fn write_pfm(stream: &mut dyn io::Write, canvas: &Canvas) -> io::Result<()> {
+    // A negative scale marks the data as little-endian
+    stream.write_all(format!("PF\n{} {}\n-1.0\n", canvas.width, canvas.height).as_bytes())?;
+    let mut line = Vec::with_capacity(canvas.width as usize * 12);
+    // Scanlines are stored from the bottom of the image to the top
+    for j in (0..canvas.height).rev() {
+        line.clear();
+        for color in canvas.row(j) {
+            for value in [color.x(), color.y(), color.z()] {
+                line.extend_from_slice(&(value as f32).to_le_bytes());
+            }
+        }
+        stream.write_all(&line)?;
+    }
+    Ok(())
+}
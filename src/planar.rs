@@ -0,0 +1,237 @@
+use std::f64::consts::PI;
+use std::ops::Range;
+use std::option::Option;
+
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vector::{Point3, Vector3};
+
+// Rays closer to parallel with a plane than this, as the cosine of the angle between the ray and
+// the normal, are treated as missing it
+const PARALLEL_EPSILON: f64 = 1e-12;
+
+// Distance along a ray to the plane through `point` with the given unit normal
+fn intersect_plane(ray: &Ray, point: Point3, normal: Vector3, range: &Range<f64>) -> Option<f64> {
+    // The direction is not always a unit vector, as in the scaled space of an instance, so the
+    // threshold is scaled by its length, compared squared to save taking a root
+    let denominator = normal.dot(ray.direction);
+    if denominator * denominator
+        <= PARALLEL_EPSILON * PARALLEL_EPSILON * ray.direction.length_squared()
+    {
+        return None;
+    }
+    let t = normal.dot(point - ray.origin) / denominator;
+    if range.contains(&t) {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+// Two unit vectors that, together with the unit normal, form a right-handed orthonormal basis
+pub fn tangent_basis(normal: Vector3) -> (Vector3, Vector3) {
+    let helper = if normal.x().abs() > 0.9 {
+        Vector3::new(0., 1., 0.)
+    } else {
+        Vector3::new(1., 0., 0.)
+    };
+    let tangent = helper.cross(normal).unit();
+    (tangent, normal.cross(tangent))
+}
+
+// A hit on a flat shape, which has no inside and so can be hit from either side. The geometric
+// normal keeps the shape's own orientation, but the shading normal is turned to face the ray so
+// that materials scatter light back to the side it came from
+fn flat_hit<'a>(
+    ray: &Ray,
+    t: f64,
+    normal: Vector3,
+    uv: (f64, f64),
+    material: &'a dyn Material,
+) -> Hit<'a> {
+    Hit {
+        point: ray.at(t),
+        normal: if normal.dot(ray.direction) > 0. {
+            -normal
+        } else {
+            normal
+        },
+        geometric_normal: normal,
+        distance: t,
+        uv,
+        barycentric: None,
+        color: None,
+        material,
+    }
+}
+
+// An infinite plane through a point. Texture coordinates are distances along two perpendicular
+// directions in the plane, so a texture repeats once per unit of distance
+pub struct Plane {
+    pub point: Point3,
+    pub normal: Vector3,
+    pub material: Box<dyn Material>,
+}
+
+impl Hittable for Plane {
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>> {
+        let normal = self.normal.unit();
+        let t = intersect_plane(ray, self.point, normal, range)?;
+        let (tangent, bitangent) = tangent_basis(normal);
+        let offset = ray.at(t) - self.point;
+        Some(flat_hit(
+            ray,
+            t,
+            normal,
+            (offset.dot(tangent), offset.dot(bitangent)),
+            self.material.as_ref(),
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::unbounded()
+    }
+}
+
+// A circular disk. Texture coordinates are polar: u is the angle around the center as a fraction
+// of a full turn, and v the distance from the center as a fraction of the radius
+pub struct Disk {
+    pub center: Point3,
+    pub normal: Vector3,
+    pub radius: f64,
+    pub material: Box<dyn Material>,
+}
+
+impl Hittable for Disk {
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>> {
+        let normal = self.normal.unit();
+        let t = intersect_plane(ray, self.center, normal, range)?;
+        let offset = ray.at(t) - self.center;
+        let distance = offset.length();
+        if distance > self.radius {
+            return None;
+        }
+        let (tangent, bitangent) = tangent_basis(normal);
+        let angle = offset.dot(bitangent).atan2(offset.dot(tangent)) + PI;
+        Some(flat_hit(
+            ray,
+            t,
+            normal,
+            (angle / (2. * PI), distance / self.radius),
+            self.material.as_ref(),
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // Along each axis the disk extends by the radius times the sine of the angle between the
+        // axis and the normal
+        let normal = self.normal.unit();
+        let extent = Vector3::new(
+            (1. - normal.x() * normal.x()).max(0.).sqrt(),
+            (1. - normal.y() * normal.y()).max(0.).sqrt(),
+            (1. - normal.z() * normal.z()).max(0.).sqrt(),
+        ) * self.radius;
+        Aabb::new(self.center - extent, self.center + extent)
+    }
+}
+
+// A parallelogram with one corner at `corner` and sides `u` and `v`. The front face is the one
+// from which `v` is counter-clockwise from `u`, and the texture coordinates are the fractions of
+// each side covered, from 0 to 1
+pub struct Quad {
+    pub corner: Point3,
+    pub u: Vector3,
+    pub v: Vector3,
+    pub material: Box<dyn Material>,
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>> {
+        let n = self.u.cross(self.v);
+        let normal = n.unit();
+        let t = intersect_plane(ray, self.corner, normal, range)?;
+
+        // Express the hit point in the coordinates of the two sides
+        let offset = ray.at(t) - self.corner;
+        let w = n / n.dot(n);
+        let a = w.dot(offset.cross(self.v));
+        let b = w.dot(self.u.cross(offset));
+        if !(0. ..=1.).contains(&a) || !(0. ..=1.).contains(&b) {
+            return None;
+        }
+        Some(flat_hit(ray, t, normal, (a, b), self.material.as_ref()))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let far = self.corner + self.u + self.v;
+        Aabb::new(self.corner, far).union(Aabb::new(self.corner + self.u, self.corner + self.v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::LambertianMaterial;
+    use crate::vector::Color3;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    // A unit square in the z = 0 plane, facing +z
+    fn quad() -> Quad {
+        Quad {
+            corner: Point3::new(0., 0., 0.),
+            u: Vector3::new(1., 0., 0.),
+            v: Vector3::new(0., 1., 0.),
+            material: Box::new(LambertianMaterial {
+                albedo: Color3::new(0.5, 0.5, 0.5),
+            }),
+        }
+    }
+
+    #[test]
+    fn quad_hit_from_front() {
+        let quad = quad();
+        let ray = Ray::new(Point3::new(0.5, 0.5, 2.), Vector3::new(0., 0., -1.), 0.);
+        let hit = quad.hit(&ray, &(0. ..f64::INFINITY)).unwrap();
+        assert_eq!(hit.distance, 2.);
+        assert_eq!(hit.normal, Vector3::new(0., 0., 1.));
+        assert_eq!(hit.geometric_normal, Vector3::new(0., 0., 1.));
+    }
+
+    #[test]
+    fn quad_hit_along_short_direction() {
+        // As in the space of an instance scaled up a million million times
+        let quad = quad();
+        let ray = Ray {
+            origin: Point3::new(0.5, 0.5, 2.),
+            direction: Vector3::new(0., 0., -1e-13),
+            time: 0.,
+        };
+        let hit = quad.hit(&ray, &(0. ..f64::INFINITY)).unwrap();
+        assert!((hit.distance - 2e13).abs() < 1.);
+    }
+
+    #[test]
+    fn quad_hit_from_behind() {
+        let quad = quad();
+        let ray = Ray::new(Point3::new(0.5, 0.5, -2.), Vector3::new(0., 0., 1.), 0.);
+        let hit = quad.hit(&ray, &(0. ..f64::INFINITY)).unwrap();
+        assert_eq!(hit.distance, 2.);
+        // The shading normal faces the ray, while the geometric normal keeps the quad's side
+        assert_eq!(hit.normal, Vector3::new(0., 0., -1.));
+        assert_eq!(hit.geometric_normal, Vector3::new(0., 0., 1.));
+
+        // So diffuse light bounces back to the side the ray came from, and only light from that
+        // side is gathered
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..100 {
+            let scattered = hit.material.scatter(&ray, &hit, &mut rng).unwrap();
+            assert!(scattered.ray.direction.z() <= 0.);
+        }
+        let behind = hit.material.evaluate(&ray, &hit, Vector3::new(0., 0., 1.));
+        assert_eq!(behind.unwrap().pdf, 0.);
+        let front = hit.material.evaluate(&ray, &hit, Vector3::new(0., 0., -1.));
+        assert!(front.unwrap().pdf > 0.);
+    }
+}
@@ -0,0 +1,475 @@
+use std::{
+    fs,
+    io::{self, BufRead},
+    path::Path,
+};
+
+use crate::{
+    material::LambertianMaterial,
+    mesh::{self, MeshData, MeshError, MeshTriangle},
+    vector::{Color3, Point3, Vector3},
+};
+
+// Load a Stanford PLY model in ASCII or binary form. Vertex positions are required, and per-vertex
+// normals, texture coordinates and colors are used when present. Faces with more than three
+// vertices are split into triangles around their first vertex
+pub fn load(path: &Path) -> Result<MeshData, MeshError> {
+    let mut stream = io::BufReader::new(fs::File::open(path)?);
+    read(&mut stream, path)
+}
+
+// Read a PLY model. `path` is only used in error messages
+pub fn read(stream: &mut dyn BufRead, path: &Path) -> Result<MeshData, MeshError> {
+    let syntax = |line: usize, message: String| MeshError::Syntax {
+        file: path.to_path_buf(),
+        line,
+        message,
+    };
+    let invalid = |message: String| MeshError::Invalid {
+        file: path.to_path_buf(),
+        message,
+    };
+
+    let (format, elements, header_lines) = read_header(stream).map_err(|err| match err {
+        HeaderError::Io(err) => MeshError::Io(err),
+        HeaderError::Syntax(line, message) => syntax(line, message),
+    })?;
+    let mut body = match format {
+        Format::Ascii => Body::Ascii {
+            stream,
+            line: header_lines,
+            tokens: Vec::new(),
+            next: 0,
+        },
+        Format::BinaryLittleEndian => Body::Binary {
+            stream,
+            big_endian: false,
+        },
+        Format::BinaryBigEndian => Body::Binary {
+            stream,
+            big_endian: true,
+        },
+    };
+
+    let mut mesh = MeshData::default();
+    let mut faces: Vec<Vec<usize>> = Vec::new();
+    let mut has_normals = false;
+    let mut has_uvs = false;
+    let mut has_colors = false;
+    // The values of each property of the current element, reused from one element to the next
+    let mut values: Vec<Vec<f64>> = Vec::new();
+
+    for element in &elements {
+        let vertex = if element.name == "vertex" {
+            Some(VertexLayout::new(element).map_err(&invalid)?)
+        } else {
+            None
+        };
+        let face_indices = if element.name == "face" {
+            let index = element
+                .property("vertex_indices")
+                .or_else(|| element.property("vertex_index"))
+                .ok_or_else(|| invalid("face element has no vertex_indices property".into()))?;
+            Some(index)
+        } else {
+            None
+        };
+        if let Some(layout) = &vertex {
+            has_normals = layout.normal.is_some();
+            has_uvs = layout.uv.is_some();
+            has_colors = layout.color.is_some();
+        }
+
+        values.resize(element.properties.len(), Vec::new());
+        for number in 0..element.count {
+            body.read_element(element, &mut values)
+                .map_err(|err| match err {
+                    BodyError::Io(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                        invalid(format!("file is truncated in {} {}", element.name, number))
+                    }
+                    BodyError::Io(err) => MeshError::Io(err),
+                    BodyError::Syntax(line, message) => syntax(line, message),
+                    BodyError::Invalid(message) => {
+                        invalid(format!("{} {}: {}", element.name, number, message))
+                    }
+                })?;
+            if let Some(layout) = &vertex {
+                let [x, y, z] = layout.position;
+                mesh.positions
+                    .push(Point3::new(values[x][0], values[y][0], values[z][0]));
+                if let Some([x, y, z]) = layout.normal {
+                    mesh.normals
+                        .push(Vector3::new(values[x][0], values[y][0], values[z][0]));
+                }
+                if let Some([u, v]) = layout.uv {
+                    mesh.uvs.push((values[u][0], values[v][0]));
+                }
+                if let Some(([r, g, b], scale)) = layout.color {
+                    // Colors in files are display values, so undo the gamma of 2 that the image
+                    // writers apply to get back the same color on a white surface
+                    let channel = |i: usize| (values[i][0] / scale).powi(2);
+                    mesh.colors
+                        .push(Color3::new(channel(r), channel(g), channel(b)));
+                }
+            } else if let Some(index) = face_indices {
+                if values[index].iter().any(|&i| i < 0.) {
+                    return Err(invalid(format!(
+                        "face {} has a negative vertex index",
+                        number
+                    )));
+                }
+                faces.push(values[index].iter().map(|&i| i as usize).collect());
+            }
+        }
+    }
+
+    mesh.materials.push(if has_colors {
+        // Let vertex colors show through unchanged
+        Box::new(LambertianMaterial {
+            albedo: Color3::new(1., 1., 1.),
+        })
+    } else {
+        mesh::default_material()
+    });
+    for (number, face) in faces.iter().enumerate() {
+        if face.len() < 3 {
+            return Err(invalid(format!(
+                "face {} has fewer than three vertices",
+                number
+            )));
+        }
+        if let Some(index) = face.iter().find(|&&i| i >= mesh.positions.len()) {
+            return Err(invalid(format!(
+                "face {} refers to vertex {}, but there are only {} vertices",
+                number,
+                index,
+                mesh.positions.len()
+            )));
+        }
+        for i in 1..face.len() - 1 {
+            let indices = [face[0], face[i], face[i + 1]];
+            mesh.triangles.push(MeshTriangle {
+                positions: indices,
+                normals: if has_normals { Some(indices) } else { None },
+                uvs: if has_uvs { Some(indices) } else { None },
+                colors: if has_colors { Some(indices) } else { None },
+                material: 0,
+            });
+        }
+    }
+    Ok(mesh)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    // The value that stands for full intensity when a color is stored in this type
+    fn color_scale(self) -> f64 {
+        match self {
+            Scalar::U8 => 255.,
+            Scalar::U16 => 65535.,
+            _ => 1.,
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    // A variable length list, stored as its length followed by its items
+    List(String, Scalar, Scalar),
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn property(&self, name: &str) -> Option<usize> {
+        self.properties.iter().position(|property| match property {
+            Property::Scalar(n, _) | Property::List(n, _, _) => n == name,
+        })
+    }
+
+    // Index of a scalar property with one of the given names, along with its type
+    fn scalar(&self, names: &[&str]) -> Option<(usize, Scalar)> {
+        self.properties
+            .iter()
+            .enumerate()
+            .find_map(|(i, property)| match property {
+                Property::Scalar(name, ty) if names.contains(&name.as_str()) => Some((i, *ty)),
+                _ => None,
+            })
+    }
+}
+
+// Where to find each vertex attribute among the properties of the vertex element
+struct VertexLayout {
+    position: [usize; 3],
+    normal: Option<[usize; 3]>,
+    uv: Option<[usize; 2]>,
+    color: Option<([usize; 3], f64)>,
+}
+
+impl VertexLayout {
+    fn new(element: &Element) -> Result<Self, String> {
+        let all = |names: &[&[&str]]| -> Option<Vec<(usize, Scalar)>> {
+            names.iter().map(|names| element.scalar(names)).collect()
+        };
+        let position = all(&[&["x"], &["y"], &["z"]])
+            .ok_or_else(|| "vertex element needs x, y and z properties".to_string())?;
+        let normal = all(&[&["nx"], &["ny"], &["nz"]]);
+        let uv = all(&[
+            &["u", "s", "texture_u", "texture_s"],
+            &["v", "t", "texture_v", "texture_t"],
+        ]);
+        let color = all(&[
+            &["red", "diffuse_red"],
+            &["green", "diffuse_green"],
+            &["blue", "diffuse_blue"],
+        ]);
+        Ok(Self {
+            position: [position[0].0, position[1].0, position[2].0],
+            normal: normal.map(|n| [n[0].0, n[1].0, n[2].0]),
+            uv: uv.map(|uv| [uv[0].0, uv[1].0]),
+            color: color.map(|c| ([c[0].0, c[1].0, c[2].0], c[0].1.color_scale())),
+        })
+    }
+}
+
+enum HeaderError {
+    Io(io::Error),
+    Syntax(usize, String),
+}
+
+impl From<io::Error> for HeaderError {
+    fn from(err: io::Error) -> Self {
+        HeaderError::Io(err)
+    }
+}
+
+// Returns the format, the elements in the order they are stored, and the number of header lines
+fn read_header(stream: &mut dyn BufRead) -> Result<(Format, Vec<Element>, usize), HeaderError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut number = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if stream.read_line(&mut line)? == 0 {
+            return Err(HeaderError::Syntax(
+                number,
+                "file ends before end_header".into(),
+            ));
+        }
+        number += 1;
+        let syntax = |message: &str| HeaderError::Syntax(number, message.into());
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if number == 1 {
+            if words != ["ply"] {
+                return Err(syntax("not a PLY file"));
+            }
+            continue;
+        }
+        match words[..] {
+            ["end_header"] => break,
+            ["format", name, _version] => {
+                format = Some(match name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(syntax("unknown format")),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| syntax("invalid element count"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| syntax("property before any element"))?;
+                let count = Scalar::parse(count).ok_or_else(|| syntax("unknown property type"))?;
+                let item = Scalar::parse(item).ok_or_else(|| syntax("unknown property type"))?;
+                if matches!(count, Scalar::F32 | Scalar::F64) {
+                    return Err(syntax("list lengths must be integers"));
+                }
+                element
+                    .properties
+                    .push(Property::List(name.to_string(), count, item));
+            }
+            ["property", ty, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| syntax("property before any element"))?;
+                let ty = Scalar::parse(ty).ok_or_else(|| syntax("unknown property type"))?;
+                element
+                    .properties
+                    .push(Property::Scalar(name.to_string(), ty));
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(syntax("unrecognized header line")),
+        }
+    }
+    match format {
+        Some(format) => Ok((format, elements, number)),
+        None => Err(HeaderError::Syntax(number, "missing format line".into())),
+    }
+}
+
+enum BodyError {
+    Io(io::Error),
+    Syntax(usize, String),
+    Invalid(String),
+}
+
+impl From<io::Error> for BodyError {
+    fn from(err: io::Error) -> Self {
+        BodyError::Io(err)
+    }
+}
+
+// The data following the header, which holds the values of every element in turn
+enum Body<'a> {
+    // One element per line, as whitespace separated numbers
+    Ascii {
+        stream: &'a mut dyn BufRead,
+        line: usize,
+        tokens: Vec<String>,
+        next: usize,
+    },
+    Binary {
+        stream: &'a mut dyn BufRead,
+        big_endian: bool,
+    },
+}
+
+impl Body<'_> {
+    fn read_element(
+        &mut self,
+        element: &Element,
+        values: &mut [Vec<f64>],
+    ) -> Result<(), BodyError> {
+        if let Body::Ascii {
+            stream,
+            line,
+            tokens,
+            next,
+        } = self
+        {
+            let mut text = String::new();
+            loop {
+                text.clear();
+                if stream.read_line(&mut text)? == 0 {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                }
+                *line += 1;
+                if !text.trim().is_empty() {
+                    break;
+                }
+            }
+            *tokens = text.split_whitespace().map(String::from).collect();
+            *next = 0;
+        }
+        for (property, values) in element.properties.iter().zip(values.iter_mut()) {
+            values.clear();
+            match property {
+                Property::Scalar(_, ty) => values.push(self.read_scalar(*ty)?),
+                Property::List(_, count, item) => {
+                    let count = self.read_scalar(*count)?;
+                    if count < 0. {
+                        return Err(self.error("negative list length".into()));
+                    }
+                    for _ in 0..count as usize {
+                        values.push(self.read_scalar(*item)?);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_scalar(&mut self, ty: Scalar) -> Result<f64, BodyError> {
+        match self {
+            Body::Ascii {
+                line, tokens, next, ..
+            } => {
+                let token = tokens
+                    .get(*next)
+                    .ok_or_else(|| BodyError::Syntax(*line, "too few values for element".into()))?;
+                *next += 1;
+                token
+                    .parse()
+                    .map_err(|_| BodyError::Syntax(*line, format!("invalid number '{}'", token)))
+            }
+            Body::Binary { stream, big_endian } => {
+                let mut buffer = [0u8; 8];
+                let bytes = &mut buffer[..ty.size()];
+                stream.read_exact(bytes)?;
+                if *big_endian {
+                    bytes.reverse();
+                }
+                let b = buffer;
+                Ok(match ty {
+                    Scalar::I8 => b[0] as i8 as f64,
+                    Scalar::U8 => b[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Scalar::F64 => f64::from_le_bytes(b),
+                })
+            }
+        }
+    }
+
+    fn error(&self, message: String) -> BodyError {
+        match self {
+            Body::Ascii { line, .. } => BodyError::Syntax(*line, message),
+            Body::Binary { .. } => BodyError::Invalid(message),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::ops::Range;
+
+// Bisection steps allowed per root. Each step at least halves the bracket, so this is enough to
+// reach the precision of an f64 from any finite interval
+const MAX_ITERATIONS: usize = 100;
+
+// Value of a polynomial, given by its coefficients from the constant term up, at x
+pub fn evaluate(coefficients: &[f64], x: f64) -> f64 {
+    coefficients.iter().rev().fold(0., |value, c| value * x + c)
+}
+
+fn derivative(coefficients: &[f64]) -> Vec<f64> {
+    coefficients
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(power, c)| c * power as f64)
+        .collect()
+}
+
+// The real roots of a polynomial, given by its coefficients from the constant term up, that lie
+// in a finite interval, in increasing order. A root where the polynomial touches zero without
+// crossing it, such as where a ray grazes a surface, is found once, wherever the polynomial comes
+// within its rounding error of zero.
+//
+// Rather than using closed-form solutions, which lose badly to cancellation for quartics, the
+// interval is split at the roots of the derivative. The polynomial is monotonic between them, so
+// each piece holds at most one root, which is then found by safeguarded Newton iteration
+pub fn roots(coefficients: &[f64], interval: Range<f64>) -> Vec<f64> {
+    // Drop vanishing leading terms so that the degree is the true one
+    let degree = match coefficients.iter().rposition(|&c| c != 0.) {
+        Some(degree) => degree,
+        None => return Vec::new(),
+    };
+    let coefficients = &coefficients[..=degree];
+    match degree {
+        0 => return Vec::new(),
+        1 => {
+            let root = -coefficients[0] / coefficients[1];
+            return if interval.contains(&root) {
+                vec![root]
+            } else {
+                Vec::new()
+            };
+        }
+        _ => {}
+    }
+
+    // Values this close to zero are lost in the rounding error of evaluating the polynomial, so a
+    // root touching zero may come out slightly to either side
+    let magnitudes: Vec<f64> = coefficients.iter().map(|c| c.abs()).collect();
+    let is_zero = |x: f64, fx: f64| {
+        fx.abs() <= 2. * degree as f64 * f64::EPSILON * evaluate(&magnitudes, x.abs())
+    };
+
+    let slope = derivative(coefficients);
+    let mut bounds = vec![interval.start];
+    bounds.extend(roots(&slope, interval.clone()));
+    bounds.push(interval.end);
+
+    let mut found = Vec::new();
+    for piece in bounds.windows(2) {
+        let (a, b) = (piece[0], piece[1]);
+        let (fa, fb) = (evaluate(coefficients, a), evaluate(coefficients, b));
+        if is_zero(a, fa) {
+            if found.last() != Some(&a) {
+                found.push(a);
+            }
+        } else if !is_zero(b, fb) && fa.signum() != fb.signum() {
+            found.push(bracketed_root(coefficients, &slope, a, b, fa));
+        }
+    }
+    found
+}
+
+// The root of a polynomial inside [a, b], where it changes sign exactly once. Newton steps are
+// taken when they stay inside the bracket, and bisection steps otherwise
+fn bracketed_root(coefficients: &[f64], slope: &[f64], mut a: f64, mut b: f64, fa: f64) -> f64 {
+    let rising = fa < 0.;
+    let mut x = (a + b) / 2.;
+    for _ in 0..MAX_ITERATIONS {
+        let fx = evaluate(coefficients, x);
+        if fx == 0. {
+            return x;
+        }
+        // Shrink the bracket to the side that still contains the sign change
+        if (fx < 0.) == rising {
+            a = x;
+        } else {
+            b = x;
+        }
+        let newton = x - fx / evaluate(slope, x);
+        let next = if newton > a && newton < b {
+            newton
+        } else {
+            (a + b) / 2.
+        };
+        let tolerance = f64::EPSILON * (1. + x.abs());
+        if (next - x).abs() <= tolerance || b - a <= tolerance {
+            return next;
+        }
+        x = next;
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Coefficients, from the constant term up, of the monic polynomial with the given roots
+    fn from_roots(roots: &[f64]) -> Vec<f64> {
+        roots.iter().fold(vec![1.], |coefficients, root| {
+            let mut product = vec![0.; coefficients.len() + 1];
+            for (power, c) in coefficients.iter().enumerate() {
+                product[power + 1] += c;
+                product[power] -= c * root;
+            }
+            product
+        })
+    }
+
+    fn assert_roots(found: &[f64], expected: &[f64]) {
+        assert_eq!(found.len(), expected.len(), "{:?} != {:?}", found, expected);
+        for (x, y) in found.iter().zip(expected) {
+            assert!((x - y).abs() < 1e-9, "{:?} != {:?}", found, expected);
+        }
+    }
+
+    #[test]
+    fn quartic_roots() {
+        let coefficients = from_roots(&[1., 2., 3., 4.]);
+        assert_eq!(coefficients, [24., -50., 35., -10., 1.]);
+        assert_roots(&roots(&coefficients, 0. ..5.), &[1., 2., 3., 4.]);
+        assert_roots(&roots(&coefficients, 1.5..3.5), &[2., 3.]);
+    }
+
+    #[test]
+    fn close_quartic_roots() {
+        let expected = [-0.5, 0.999, 1.001, 250.];
+        assert_roots(&roots(&from_roots(&expected), -10. ..1000.), &expected);
+    }
+
+    #[test]
+    fn double_root() {
+        assert_roots(&roots(&from_roots(&[1., 1., 3.]), 0. ..5.), &[1., 3.]);
+        assert_roots(&roots(&from_roots(&[1.3, 1.3, 3.]), 0. ..5.), &[1.3, 3.]);
+        assert_roots(
+            &roots(&from_roots(&[-2., 0.7, 0.7, 4.]), -5. ..5.),
+            &[-2., 0.7, 4.],
+        );
+    }
+
+    #[test]
+    fn roots_at_ends_of_interval() {
+        let coefficients = from_roots(&[1., 3.]);
+        // The interval is half-open, like the ranges rays are tested against
+        assert_roots(&roots(&coefficients, 1. ..3.), &[1.]);
+        assert_roots(&roots(&coefficients, 1. ..3.5), &[1., 3.]);
+        assert_roots(
+            &roots(&from_roots(&[0., 2., 5., 6.]), 0. ..6.),
+            &[0., 2., 5.],
+        );
+    }
+
+    #[test]
+    fn leading_zero_coefficients() {
+        let mut coefficients = from_roots(&[1., 2., 3., 4.]);
+        coefficients.extend([0., 0.]);
+        assert_roots(&roots(&coefficients, 0. ..5.), &[1., 2., 3., 4.]);
+        assert_roots(&roots(&[2., -1., 0., 0., 0.], 0. ..5.), &[2.]);
+        assert_roots(&roots(&[-4., 0., 1., 0.], 0. ..5.), &[2.]);
+        assert_roots(&roots(&[3., 0., 0.], 0. ..5.), &[]);
+        assert_roots(&roots(&[0., 0., 0.], 0. ..5.), &[]);
+    }
+
+    #[test]
+    fn no_roots() {
+        assert_roots(&roots(&[1., 0., 1.], -10. ..10.), &[]);
+        assert_roots(&roots(&from_roots(&[1., 2., 3., 4.]), 5. ..10.), &[]);
+    }
+}
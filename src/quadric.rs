@@ -0,0 +1,337 @@
+use std::f64::consts::PI;
+use std::ops::Range;
+use std::option::Option;
+
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable};
+use crate::material::Material;
+use crate::planar::tangent_basis;
+use crate::ray::Ray;
+use crate::vector::{Point3, Vector3};
+
+// The quadrics below are all surfaces of revolution, placed with a base point and an axis. Heights
+// are measured along the axis from the base, and angles around it from an arbitrary direction
+// perpendicular to it. Each can be clipped to a range of heights and to a sweep angle in degrees
+// less than a full turn, and capped with flat disks at the ends of its height range to make a
+// closed solid. Texture coordinates run around the surface in u and up it in v, both from 0 to 1,
+// and out from the center of the caps in v
+
+// A cylinder standing on its base, with the given height
+pub struct Cylinder {
+    pub base: Point3,
+    pub axis: Vector3,
+    pub radius: f64,
+    pub height: f64,
+    pub sweep: f64,
+    pub capped: bool,
+    pub material: Box<dyn Material>,
+}
+
+impl Cylinder {
+    fn revolution(&self) -> Revolution {
+        Revolution::new(
+            self.base,
+            self.axis,
+            [self.radius * self.radius, 0., 0.],
+            0. ..self.height,
+            self.sweep,
+            self.capped,
+        )
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>> {
+        self.revolution().hit(ray, range, self.material.as_ref())
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.revolution().bounding_box()
+    }
+}
+
+// A cone with the given radius at its base, narrowing to a point at the given height
+pub struct Cone {
+    pub base: Point3,
+    pub axis: Vector3,
+    pub radius: f64,
+    pub height: f64,
+    pub span: Range<f64>,
+    pub sweep: f64,
+    pub capped: bool,
+    pub material: Box<dyn Material>,
+}
+
+impl Cone {
+    fn revolution(&self) -> Revolution {
+        // r = radius (1 - h / height)
+        let r2 = self.radius * self.radius;
+        Revolution::new(
+            self.base,
+            self.axis,
+            [r2, -2. * r2 / self.height, r2 / (self.height * self.height)],
+            self.span.clone(),
+            self.sweep,
+            self.capped,
+        )
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>> {
+        self.revolution().hit(ray, range, self.material.as_ref())
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.revolution().bounding_box()
+    }
+}
+
+// A paraboloid with its tip at the base, widening to the given radius at the given height
+pub struct Paraboloid {
+    pub base: Point3,
+    pub axis: Vector3,
+    pub radius: f64,
+    pub height: f64,
+    pub span: Range<f64>,
+    pub sweep: f64,
+    pub capped: bool,
+    pub material: Box<dyn Material>,
+}
+
+impl Paraboloid {
+    fn revolution(&self) -> Revolution {
+        // r² = radius² h / height
+        Revolution::new(
+            self.base,
+            self.axis,
+            [0., self.radius * self.radius / self.height, 0.],
+            self.span.clone(),
+            self.sweep,
+            self.capped,
+        )
+    }
+}
+
+impl Hittable for Paraboloid {
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>> {
+        self.revolution().hit(ray, range, self.material.as_ref())
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.revolution().bounding_box()
+    }
+}
+
+// A hyperboloid of one sheet, with its narrowest radius at the base and widening symmetrically
+// above and below it to `end_radius` at a distance of `height`
+pub struct Hyperboloid {
+    pub base: Point3,
+    pub axis: Vector3,
+    pub radius: f64,
+    pub end_radius: f64,
+    pub height: f64,
+    pub span: Range<f64>,
+    pub sweep: f64,
+    pub capped: bool,
+    pub material: Box<dyn Material>,
+}
+
+impl Hyperboloid {
+    fn revolution(&self) -> Revolution {
+        // r² = radius² + (end_radius² - radius²) (h / height)²
+        let r2 = self.radius * self.radius;
+        Revolution::new(
+            self.base,
+            self.axis,
+            [
+                r2,
+                0.,
+                (self.end_radius * self.end_radius - r2) / (self.height * self.height),
+            ],
+            self.span.clone(),
+            self.sweep,
+            self.capped,
+        )
+    }
+}
+
+impl Hittable for Hyperboloid {
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>> {
+        self.revolution().hit(ray, range, self.material.as_ref())
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.revolution().bounding_box()
+    }
+}
+
+// A surface of revolution whose squared radius is a quadratic in the height, r² = a + b h + c h²,
+// with `profile` holding a, b and c. The radius must not be larger inside the height range than
+// at its ends
+struct Revolution {
+    base: Point3,
+    // The unit axis and two unit directions perpendicular to it that angles are measured in
+    axis: Vector3,
+    tangent: Vector3,
+    bitangent: Vector3,
+    profile: [f64; 3],
+    span: Range<f64>,
+    sweep: f64, // radians
+    capped: bool,
+}
+
+impl Revolution {
+    fn new(
+        base: Point3,
+        axis: Vector3,
+        profile: [f64; 3],
+        span: Range<f64>,
+        sweep: f64,
+        capped: bool,
+    ) -> Self {
+        let axis = axis.unit();
+        let (tangent, bitangent) = tangent_basis(axis);
+        Self {
+            base,
+            axis,
+            tangent,
+            bitangent,
+            profile,
+            span,
+            sweep: sweep.clamp(0., 360.).to_radians(),
+            capped,
+        }
+    }
+
+    fn radius_squared(&self, height: f64) -> f64 {
+        let [a, b, c] = self.profile;
+        a + b * height + c * height * height
+    }
+
+    // A vector in coordinates across, along and across the axis
+    fn local(&self, v: Vector3) -> Vector3 {
+        Vector3::new(v.dot(self.tangent), v.dot(self.axis), v.dot(self.bitangent))
+    }
+
+    fn world(&self, v: Vector3) -> Vector3 {
+        self.tangent * v.x() + self.axis * v.y() + self.bitangent * v.z()
+    }
+
+    // Angle around the axis of a point in local coordinates, from 0 to 2π
+    fn angle(&self, local: Vector3) -> f64 {
+        local.z().atan2(local.x()).rem_euclid(2. * PI)
+    }
+
+    fn hit<'a>(
+        &self,
+        ray: &Ray,
+        range: &Range<f64>,
+        material: &'a dyn Material,
+    ) -> Option<Hit<'a>> {
+        let origin = self.local(ray.origin - self.base);
+        let direction = self.local(ray.direction);
+        let [_, b, c] = self.profile;
+        let heights = self.span.start..=self.span.end;
+
+        // The closest hit so far as a distance, a local normal and texture coordinates. The end of
+        // the range is pulled in as hits are found so that only closer ones are considered
+        let mut closest = None;
+        let mut range = range.clone();
+
+        // Substituting the ray into x² + z² = a + b h + c h² gives a quadratic in the distance
+        let qa = direction.x() * direction.x() + direction.z() * direction.z()
+            - c * direction.y() * direction.y();
+        let qb = origin.x() * direction.x() + origin.z() * direction.z()
+            - (b / 2. + c * origin.y()) * direction.y();
+        let qc =
+            origin.x() * origin.x() + origin.z() * origin.z() - self.radius_squared(origin.y());
+        if let Some((near, far)) = solve_quadratic(qa, qb, qc) {
+            for t in [near, far] {
+                if !range.contains(&t) {
+                    continue;
+                }
+                let p = origin + direction * t;
+                let angle = self.angle(p);
+                if !heights.contains(&p.y()) || angle > self.sweep {
+                    continue;
+                }
+                // Half the gradient of x² + z² - r²(h), which points out of the solid
+                let normal = Vector3::new(p.x(), -(b / 2. + c * p.y()), p.z());
+                let v = (p.y() - self.span.start) / (self.span.end - self.span.start);
+                closest = Some((t, normal, (angle / self.sweep, v)));
+                range.end = t;
+                break;
+            }
+        }
+
+        if self.capped && direction.y() != 0. {
+            for (height, side) in [(self.span.start, -1.), (self.span.end, 1.)] {
+                let radius_squared = self.radius_squared(height);
+                let t = (height - origin.y()) / direction.y();
+                if radius_squared <= 0. || !range.contains(&t) {
+                    continue;
+                }
+                let p = origin + direction * t;
+                let distance_squared = p.x() * p.x() + p.z() * p.z();
+                let angle = self.angle(p);
+                if distance_squared > radius_squared || angle > self.sweep {
+                    continue;
+                }
+                let v = (distance_squared / radius_squared).sqrt();
+                closest = Some((t, Vector3::new(0., side, 0.), (angle / self.sweep, v)));
+                range.end = t;
+            }
+        }
+
+        let (t, normal, uv) = closest?;
+        let normal = self.world(normal).unit();
+        Some(Hit {
+            point: ray.at(t),
+            normal,
+            geometric_normal: normal,
+            distance: t,
+            uv,
+            barycentric: None,
+            color: None,
+            material,
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // The widest point is at one end of the height range, so bound the cylinder of that
+        // radius around the whole range
+        let radius = self
+            .radius_squared(self.span.start)
+            .max(self.radius_squared(self.span.end))
+            .max(0.)
+            .sqrt();
+        let mut bbox = Aabb::empty();
+        for height in [self.span.start, self.span.end] {
+            for (x, z) in [(-1., -1.), (-1., 1.), (1., -1.), (1., 1.)] {
+                let corner = self.base + self.world(Vector3::new(x * radius, height, z * radius));
+                bbox = bbox.union(Aabb::new(corner, corner));
+            }
+        }
+        bbox
+    }
+}
+
+// Roots of a t² + 2 b t + c = 0 in increasing order, or None if there are none. A linear equation
+// gives its single root twice
+fn solve_quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    if a.abs() < 1e-12 {
+        if b == 0. {
+            return None;
+        }
+        let t = -c / (2. * b);
+        return Some((t, t));
+    }
+    let discriminant = b * b - a * c;
+    if discriminant < 0. {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    let (t0, t1) = ((-b - root) / a, (-b + root) / a);
+    Some((t0.min(t1), t0.max(t1)))
+}
@@ -4,13 +4,16 @@ use crate::vector::{Point3, Vector3};
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vector3,
+    // The moment the ray was traced, which moving shapes use to decide where they are
+    pub time: f64,
 }
 
 impl Ray {
-    pub fn new(origin: Point3, direction: Vector3) -> Self {
+    pub fn new(origin: Point3, direction: Vector3, time: f64) -> Self {
         Ray {
-            origin: origin,
+            origin,
             direction: direction.unit(),
+            time,
         }
     }
 
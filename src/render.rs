@@ -1,52 +1,218 @@
-use rand::{rngs::ThreadRng, Rng};
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
 use std::{
-    collections::HashMap,
-    format_args,
-    io::{self, Write},
+    cmp,
     ops::Range,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
 };
 
 use crate::{
-    hittable::Hittable,
+    background::{Background, GradientBackground},
+    canvas::Canvas,
+    hittable::{Hit, Hittable},
+    medium::{Fog, Medium},
     ray::Ray,
     vector::{Color3, Point3, Vector3},
 };
 
-const MAX_BOUNCE_DEPTH: usize = 20;
+const DEFAULT_MAX_DEPTH: usize = 20;
+const TILE_SIZE: u32 = 32;
 const TWO_PI: f64 = 2. * std::f64::consts::PI;
+// Hits closer than the start are ignored, so rays leaving a surface do not hit it again
+const SURFACE_RANGE: Range<f64> = 0.01..f64::INFINITY;
+// Most boundaries of media a ray may cross between one bounce and the next
+const MAX_BOUNDARY_CROSSINGS: usize = 64;
 
-// Resolve the color returned by a single ray by simulating it bouncing and scattered off objects in the scene
+// Resolve the color returned by a single ray by simulating it bouncing and scattered off objects in the scene.
+// `scatter_pdf` is the density with which the ray's direction was chosen when it scattered off a
+// diffuse surface, or None for camera rays and rays reflected by specular surfaces. `medium` is
+// the medium the ray is travelling through, if any
 fn compute_ray(
     ray: &Ray,
     world: &dyn Hittable,
-    rng: &mut rand::rngs::ThreadRng,
+    settings: &RenderSettings,
+    rng: &mut dyn RngCore,
     max_depth: usize,
+    scatter_pdf: Option<f64>,
+    medium: Option<&Medium>,
 ) -> Color3 {
     if max_depth == 0 {
         return Color3::new(0., 0., 0.);
     }
 
-    let hit = world.hit(
-        ray,
-        &Range {
-            start: 0.01,
-            end: f64::INFINITY,
-        },
-    );
-    match hit {
-        Some(h) => {
-            // If the ray hits something, it will bounce off in a random direction
-            let scattered = h.material.scatter(ray, &h, rng);
-            match scattered {
-                Some(s) => compute_ray(&s.ray, world, rng, max_depth - 1) * s.attentuation,
-                None => Color3::new(0., 0., 0.),
+    let (mut ray, mut medium) = (*ray, medium);
+    // The boundary of a medium is not seen itself, so the ray carries on across it, into the
+    // medium or back out into the scene's fog. Crossing one is not a bounce, so it does not count
+    // toward the depth, but a ray that keeps finding boundaries is eventually given up on
+    for _ in 0..MAX_BOUNDARY_CROSSINGS {
+        let hit = world.hit(&ray, &SURFACE_RANGE);
+
+        // A ray leaving a medium through its boundary was travelling through it, even if it did
+        // not come in through the boundary, as when the camera is inside
+        let leaving = hit.and_then(|h| {
+            h.material
+                .medium()
+                .filter(|_| ray.direction.dot(h.geometric_normal) > 0.)
+        });
+        let current = leaving.or(medium);
+
+        // Free-flight sampling: the ray scatters off a particle of the medium if it travels the
+        // sampled distance before reaching the surface, and otherwise passes through unchanged.
+        // The chance of each is the fraction of light the medium scatters or lets through, so
+        // neither needs weighting. A ray that meets no surface crosses the fog to where it ends
+        if let Some(m) = current {
+            let end = hit.map_or_else(|| settings.fog_distance(), |h| h.distance);
+            let distance = m.sample_distance(rng);
+            if distance < end {
+                let normal = -ray.direction;
+                let particle = Hit {
+                    point: ray.at(distance),
+                    normal,
+                    geometric_normal: normal,
+                    distance,
+                    uv: (0., 0.),
+                    barycentric: None,
+                    color: None,
+                    material: m,
+                };
+                return shade(&ray, &particle, world, settings, rng, max_depth, current);
             }
         }
-        None => {
-            // If the ray hits nothing, return a sky colour
-            let a = ray.direction.y() * 0.5 + 1.;
-            Color3::new(1., 1., 1.) * (1. - a) + Color3::new(0.5, 0.7, 1.) * a
+
+        let h = match hit {
+            Some(h) => h,
+            // If the ray hits nothing, it picks up the light coming from the background. When the
+            // background could also have been reached by direct sampling from the last bounce,
+            // the two estimates are combined with multiple importance sampling
+            None => {
+                let color = settings.background.color(ray.direction);
+                return match scatter_pdf {
+                    Some(pdf) => {
+                        color * power_heuristic(pdf, settings.background.pdf(ray.direction))
+                    }
+                    None => color,
+                };
+            }
+        };
+
+        match h.material.medium() {
+            Some(boundary) => {
+                medium = if leaving.is_some() {
+                    settings.fog_medium()
+                } else {
+                    Some(boundary)
+                };
+                ray = Ray::new(h.point, ray.direction, ray.time);
+            }
+            None => return shade(&ray, &h, world, settings, rng, max_depth, current),
+        }
+    }
+    Color3::new(0., 0., 0.)
+}
+
+// The light coming back along a ray from what it hit, a surface or a particle of a medium: any
+// light it emits, plus light it scatters from the background directly and from further bounces
+fn shade(
+    ray: &Ray,
+    h: &Hit,
+    world: &dyn Hittable,
+    settings: &RenderSettings,
+    rng: &mut dyn RngCore,
+    max_depth: usize,
+    medium: Option<&Medium>,
+) -> Color3 {
+    let emitted = h.material.emitted(h);
+    let direct = sample_background(ray, h, world, settings, rng, medium);
+    match h.material.scatter(ray, h, rng) {
+        Some(s) => {
+            let pdf = h
+                .material
+                .evaluate(ray, h, s.ray.direction)
+                .map(|evaluation| evaluation.pdf);
+            emitted
+                + direct
+                + compute_ray(&s.ray, world, settings, rng, max_depth - 1, pdf, medium)
+                    * s.attentuation
+        }
+        None => emitted + direct,
+    }
+}
+
+// Estimate the light reaching a diffuse surface, or a particle of `medium`, directly from the
+// background by sampling a direction toward its bright regions and checking nothing in the scene
+// is in the way
+fn sample_background(
+    ray: &Ray,
+    hit: &Hit,
+    world: &dyn Hittable,
+    settings: &RenderSettings,
+    rng: &mut dyn RngCore,
+    medium: Option<&Medium>,
+) -> Color3 {
+    let black = Color3::new(0., 0., 0.);
+    let sample = match settings.background.sample(rng) {
+        Some(sample) => sample,
+        None => return black,
+    };
+    let evaluation = match hit.material.evaluate(ray, hit, sample.direction) {
+        Some(evaluation) if evaluation.pdf > 0. => evaluation,
+        _ => return black,
+    };
+    let shadow_ray = Ray::new(hit.point, sample.direction, ray.time);
+    let visible = transmittance(&shadow_ray, world, settings, medium);
+    if visible <= 0. {
+        return black;
+    }
+    sample.radiance * evaluation.value * power_heuristic(sample.pdf, evaluation.pdf) * visible
+        / sample.pdf
+}
+
+// The fraction of the light arriving along a shadow ray from the background that reaches its
+// origin in `medium`: none if a surface is in the way, or what is left after crossing any media
+// and the fog, out to where the fog ends as for the rays traced by compute_ray
+fn transmittance(
+    ray: &Ray,
+    world: &dyn Hittable,
+    settings: &RenderSettings,
+    medium: Option<&Medium>,
+) -> f64 {
+    let (mut ray, mut medium) = (*ray, medium);
+    let mut transmittance = 1.;
+    while let Some(hit) = world.hit(&ray, &SURFACE_RANGE) {
+        let boundary = match hit.material.medium() {
+            Some(boundary) => boundary,
+            None => return 0.,
+        };
+        // Each stretch ends at a boundary, and as in compute_ray a ray leaving a medium was
+        // travelling through it wherever it started
+        let leaving = ray.direction.dot(hit.geometric_normal) > 0.;
+        if let Some(m) = if leaving { Some(boundary) } else { medium } {
+            transmittance *= m.transmittance(hit.distance);
         }
+        medium = if leaving {
+            settings.fog_medium()
+        } else {
+            Some(boundary)
+        };
+        ray = Ray::new(hit.point, ray.direction, ray.time);
+    }
+    if let Some(m) = medium {
+        transmittance *= m.transmittance(settings.fog_distance());
+    }
+    transmittance
+}
+
+// Weight for a sample drawn from a strategy with density `pdf`, when another strategy with
+// density `other_pdf` could also have produced it
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0. {
+        a / (a + b)
+    } else {
+        0.
     }
 }
 
@@ -63,22 +229,115 @@ pub struct Camera {
     defocus_angle: f64,
     defocus_disk_u: Vector3,
     defocus_disk_v: Vector3,
+    // Rays are spread evenly over the time the shutter is open, so anything that moves in that
+    // time is blurred along its path
+    shutter_open: f64,
+    shutter_close: f64,
     samples: usize,
 }
 
+// The user-facing parameters a Camera is built from
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CameraSettings {
+    pub aspect_ratio: f64,
+    pub image_height: u32,
+    pub image_width: Option<u32>, // Width in pixels, taken from the aspect ratio if not given
+    pub vertical_fov: f64,
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub up: Vector3,         // Direction that appears upward in the image
+    pub defocus_angle: f64,  // Controls size of virtual lens
+    pub focus_distance: f64, // Controls distance of virtual lens from focus plane
+    pub shutter_open: f64,   // Time the shutter opens, in the units moving objects are animated in
+    pub shutter_close: f64,  // Time the shutter closes, the same as opening for no motion blur
+    pub samples: usize,
+}
+
+impl CameraSettings {
+    pub fn camera(&self) -> Camera {
+        let image_width = self.image_width.unwrap_or_else(|| {
+            ((self.image_height as f64 * self.aspect_ratio).round() as u32).max(1)
+        });
+        Camera::new(
+            image_width,
+            self.image_height,
+            self.vertical_fov,
+            self.look_from,
+            self.look_at,
+            self.up,
+            self.defocus_angle,
+            self.focus_distance,
+            self.shutter_open..self.shutter_close,
+            self.samples,
+        )
+    }
+}
+
+// Settings that control how an image is rendered, independent of the scene being rendered
+#[derive(Clone)]
+pub struct RenderSettings {
+    pub threads: usize,                  // Number of worker threads
+    pub seed: u64,                       // Seed from which every random sample is derived
+    pub max_depth: usize, // Number of times a ray may bounce before it is considered absorbed
+    pub background: Arc<dyn Background>, // Light arriving along rays that escape the scene
+    pub fog: Option<Fog>, // Medium filling the space between the scene's shapes
+}
+
+impl Default for RenderSettings {
+    // One thread per available core, a fresh random seed and a sky gradient background
+    fn default() -> Self {
+        Self {
+            threads: thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            seed: rand::thread_rng().gen(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            background: Arc::new(GradientBackground::sky()),
+            fog: None,
+        }
+    }
+}
+
+impl RenderSettings {
+    fn fog_medium(&self) -> Option<&Medium> {
+        self.fog.as_ref().map(|fog| &fog.medium)
+    }
+
+    // How far rays that escape the scene travel through the fog, which is nowhere without fog
+    fn fog_distance(&self) -> f64 {
+        self.fog.map_or(0., |fog| fog.distance)
+    }
+}
+
+// Derive an independent random stream for a tile. The index is spread by a large odd constant so
+// that neighbouring tiles and neighbouring seeds do not produce related streams
+fn tile_rng(seed: u64, index: usize) -> StdRng {
+    StdRng::seed_from_u64(seed ^ (index as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+}
+
+// A rectangular block of pixels that is rendered as a single unit of work by one thread
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Tile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        aspect_ratio: f64,
+        image_width: u32,
         image_height: u32,
         vertical_fov: f64,
         camera_center: Point3,
         camera_lookat: Point3,
+        up: Vector3,
         defocus_angle: f64,
         focus_distance: f64,
+        shutter: Range<f64>,
         samples: usize,
     ) -> Self {
-        let image_width = (image_height as f64 * aspect_ratio) as u32;
-
         // Viewport setup
         // compute viewport based on focal length and FOV
         let fov_theta = vertical_fov.to_radians();
@@ -86,7 +345,6 @@ impl Camera {
         let viewport_width = viewport_height * (image_width as f64 / image_height as f64);
 
         // Comput a coordinate basis for the camera based on the direction it's pointing
-        let up = Vector3::new(0., 1., 0.);
         let camera_basis_w = (camera_center - camera_lookat).unit();
         let camera_basis_u = up.cross(camera_basis_w).unit();
         let camera_basis_v = camera_basis_w.cross(camera_basis_u).unit();
@@ -112,23 +370,93 @@ impl Camera {
             defocus_angle,
             defocus_disk_u: camera_basis_u * defocus_disk_radius,
             defocus_disk_v: camera_basis_v * defocus_disk_radius,
+            shutter_open: shutter.start,
+            shutter_close: shutter.end,
             samples,
         }
     }
 
-    pub fn draw(self, world: &dyn Hittable, rng: &mut ThreadRng) -> Canvas {
+    // Render the image by splitting it into tiles which are handed out to a pool of worker threads.
+    // Every tile draws from its own random stream derived from the seed, so the image is identical
+    // for a given seed no matter how many threads render it or in which order tiles finish
+    pub fn draw(self, world: &dyn Hittable, settings: &RenderSettings) -> Canvas {
+        let tiles = self.tiles();
+        let next_tile = AtomicUsize::new(0);
+
+        let rendered: Vec<(Tile, Canvas)> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..settings.threads.max(1))
+                .map(|_| {
+                    let tiles = &tiles;
+                    let next_tile = &next_tile;
+                    scope.spawn(move || {
+                        let mut finished = Vec::new();
+                        loop {
+                            // Each worker claims the next unrendered tile until none remain
+                            let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                            match tiles.get(index) {
+                                Some(&tile) => {
+                                    let mut rng = tile_rng(settings.seed, index);
+                                    finished.push((
+                                        tile,
+                                        self.draw_tile(tile, world, settings, &mut rng),
+                                    ))
+                                }
+                                None => break,
+                            }
+                        }
+                        finished
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("render thread panicked"))
+                .collect()
+        });
+
         let mut canvas = Canvas::new(self.image_width, self.image_height);
-        for i in 0..canvas.width {
-            for j in 0..canvas.height {
-                let color = self.draw_pixel(i, j, world, rng);
-                canvas.put_pixel(i, j, color);
+        for (tile, pixels) in rendered {
+            canvas.blit(&pixels, tile.x, tile.y);
+        }
+        canvas
+    }
+
+    // Divide the image into tiles of at most TILE_SIZE x TILE_SIZE pixels
+    fn tiles(self) -> Vec<Tile> {
+        let mut tiles = Vec::new();
+        for y in (0..self.image_height).step_by(TILE_SIZE as usize) {
+            for x in (0..self.image_width).step_by(TILE_SIZE as usize) {
+                tiles.push(Tile {
+                    x,
+                    y,
+                    width: TILE_SIZE.min(self.image_width - x),
+                    height: TILE_SIZE.min(self.image_height - y),
+                });
+            }
+        }
+        tiles
+    }
+
+    // Render the pixels of a single tile into a canvas the size of the tile
+    fn draw_tile(
+        self,
+        tile: Tile,
+        world: &dyn Hittable,
+        settings: &RenderSettings,
+        rng: &mut dyn RngCore,
+    ) -> Canvas {
+        let mut canvas = Canvas::new(tile.width, tile.height);
+        for (j, row) in canvas.rows_mut().enumerate() {
+            for (i, pixel) in row.iter_mut().enumerate() {
+                *pixel =
+                    self.draw_pixel(tile.x + i as u32, tile.y + j as u32, world, settings, rng);
             }
         }
         canvas
     }
 
     // Get a random point from the virtual lens to simulate depth-of-field
-    fn sample_defocus_disk(self, rng: &mut ThreadRng) -> Point3 {
+    fn sample_defocus_disk(self, rng: &mut dyn RngCore) -> Point3 {
         if self.defocus_angle <= 0. {
             return self.camera_center;
         };
@@ -138,7 +466,23 @@ impl Camera {
             + (self.defocus_disk_u * theta.cos() + self.defocus_disk_v * theta.sin()) * r
     }
 
-    fn draw_pixel(self, i: u32, j: u32, world: &dyn Hittable, rng: &mut ThreadRng) -> Color3 {
+    // Get a random moment while the shutter is open
+    fn sample_time(self, rng: &mut dyn RngCore) -> f64 {
+        // Times that are not numbers cannot be compared, so they also leave the shutter closed
+        if self.shutter_open.partial_cmp(&self.shutter_close) != Some(cmp::Ordering::Less) {
+            return self.shutter_open;
+        }
+        rng.gen_range(self.shutter_open..self.shutter_close)
+    }
+
+    fn draw_pixel(
+        self,
+        i: u32,
+        j: u32,
+        world: &dyn Hittable,
+        settings: &RenderSettings,
+        rng: &mut dyn RngCore,
+    ) -> Color3 {
         // Sample a collection of rays within the pixel and take the average color
         let pixel_center =
             self.pixel_00 + (self.pixel_delta_u * i as f64) + (self.pixel_delta_v * j as f64);
@@ -148,39 +492,18 @@ impl Camera {
             let pixel_offset = (self.pixel_delta_u * rng.gen_range(-0.5..0.5))
                 + (self.pixel_delta_v * rng.gen_range(-0.5..0.5));
             let ray_direction = pixel_center + pixel_offset - ray_origin;
-            let ray = Ray::new(ray_origin, ray_direction);
-            color += compute_ray(&ray, world, rng, MAX_BOUNCE_DEPTH);
+            let ray = Ray::new(ray_origin, ray_direction, self.sample_time(rng));
+            color += compute_ray(
+                &ray,
+                world,
+                settings,
+                rng,
+                settings.max_depth,
+                None,
+                settings.fog_medium(),
+            );
         }
         color /= self.samples as f64;
         color
     }
 }
-
-pub struct Canvas {
-    pub width: u32,
-    pub height: u32,
-    pixels: HashMap<(u32, u32), Color3>,
-    default: Color3,
-}
-
-impl Canvas {
-    pub fn new(width: u32, height: u32) -> Self {
-        Self {
-            width,
-            height,
-            pixels: HashMap::new(),
-            default: Color3::new(0., 0., 0.),
-        }
-    }
-
-    pub fn get_pixel<'a>(&'a self, x: u32, y: u32) -> &'a Color3 {
-        match self.pixels.get(&(x, y)) {
-            Some(c) => c,
-            None => &self.default,
-        }
-    }
-
-    pub fn put_pixel(&mut self, x: u32, y: u32, color: Color3) {
-        self.pixels.insert((x, y), color);
-    }
-}
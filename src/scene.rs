@@ -0,0 +1,1188 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    ops::{Range, RangeInclusive},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::{
+    background::{Background, EnvironmentMap, GradientBackground, SolidBackground},
+    csg::{Csg, CsgOperation},
+    cuboid::Cuboid,
+    gltf::{self, GltfCamera, GltfScene},
+    hittable::{Hittable, MovingSphere, Sphere, World},
+    instance::{Instance, Keyframe},
+    material::{DialectricMaterial, DiffuseLight, LambertianMaterial, Material, MirrorMaterial},
+    matrix::Matrix4,
+    medium::{Fog, Medium, PhaseFunction},
+    mesh::{self, Mesh, MeshError},
+    planar::{Disk, Plane, Quad},
+    quadric::{Cone, Cylinder, Hyperboloid, Paraboloid},
+    render::{CameraSettings, RenderSettings},
+    torus::Torus,
+    triangle::Triangle,
+    vector::{Color3, Point3, Vector3},
+};
+
+// A complete description of what to render, loaded from a TOML scene file such as:
+//
+//   [camera]
+//   image_height = 400
+//   look_from = [13, 2, 3]
+//   look_at = [0, 0, 0]
+//
+//   [background]
+//   type = "gradient"
+//   bottom = [1, 1, 1]
+//   top = [0.5, 0.7, 1]
+//
+//   [materials.ground]
+//   type = "lambertian"
+//   albedo = [0.5, 0.5, 0.5]
+//
+//   [[shapes]]
+//   type = "plane"
+//   point = [0, 0, 0]
+//   normal = [0, 1, 0]
+//   material = "ground"
+pub struct Scene {
+    pub world: World,
+    pub camera: CameraSettings,
+    pub render: RenderSettings,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    // A model file such as glTF could not be loaded
+    Model(MeshError),
+    // The file is not valid TOML or does not match the scene layout
+    Syntax { line: usize, message: String },
+    // The file is well formed but describes something that cannot be rendered
+    Invalid { line: usize, message: String },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(err) => write!(f, "{}", err),
+            SceneError::Model(err) => write!(f, "{}", err),
+            SceneError::Syntax { line, message } | SceneError::Invalid { line, message } => {
+                write!(f, "line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(err: io::Error) -> Self {
+        SceneError::Io(err)
+    }
+}
+
+impl From<MeshError> for SceneError {
+    fn from(err: MeshError) -> Self {
+        SceneError::Model(err)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    camera: Spanned<CameraDesc>,
+    render: Option<Spanned<RenderDesc>>,
+    background: Option<Spanned<BackgroundDesc>>,
+    fog: Option<Spanned<FogDesc>>,
+    #[serde(default)]
+    materials: HashMap<String, Spanned<MaterialDesc>>,
+    // Shapes that are only drawn where `instance` shapes place them
+    #[serde(default)]
+    objects: HashMap<String, Spanned<ShapeDesc>>,
+    #[serde(default)]
+    shapes: Vec<Spanned<ShapeDesc>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    #[serde(default = "default_aspect_ratio")]
+    aspect_ratio: f64,
+    image_height: u32,
+    #[serde(default = "default_vertical_fov")]
+    vertical_fov: f64,
+    look_from: [f64; 3],
+    look_at: [f64; 3],
+    #[serde(default = "default_up")]
+    up: [f64; 3],
+    #[serde(default)]
+    defocus_angle: f64,
+    #[serde(default = "default_focus_distance")]
+    focus_distance: f64,
+    // Times the shutter opens and closes. Closed instantly at time 0 unless given, so nothing is
+    // blurred by motion
+    #[serde(default)]
+    shutter: [f64; 2],
+}
+
+// Image height for glTF scenes, which do not specify one
+const DEFAULT_IMAGE_HEIGHT: u32 = 400;
+
+fn default_aspect_ratio() -> f64 {
+    16. / 9.
+}
+
+fn default_vertical_fov() -> f64 {
+    60.
+}
+
+fn default_up() -> [f64; 3] {
+    [0., 1., 0.]
+}
+
+fn default_focus_distance() -> f64 {
+    10.
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RenderDesc {
+    #[serde(default = "default_samples")]
+    samples: usize,
+    threads: Option<usize>,
+    seed: Option<u64>,
+    max_depth: Option<usize>,
+}
+
+fn default_samples() -> usize {
+    100
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackgroundDesc {
+    Solid {
+        color: [f64; 3],
+    },
+    Gradient {
+        bottom: [f64; 3],
+        top: [f64; 3],
+    },
+    Environment {
+        // Path to an equirectangular .hdr or .pfm image, relative to the scene file
+        path: PathBuf,
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+        // Degrees about the vertical axis
+        #[serde(default)]
+        rotation: f64,
+    },
+}
+
+fn default_intensity() -> f64 {
+    1.
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        albedo: [f64; 3],
+    },
+    Mirror {
+        albedo: [f64; 3],
+        #[serde(default)]
+        fuzziness: f64,
+    },
+    Dialectric {
+        refractive_index: f64,
+    },
+    DiffuseLight {
+        emit: [f64; 3],
+    },
+    // Fills the shapes it is given to, whose surfaces are not seen themselves
+    Medium(MediumDesc),
+}
+
+// A homogeneous medium of particles that scatter light evenly in every direction, or forward or
+// back for a positive or negative `anisotropy`
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+struct MediumDesc {
+    density: f64,
+    #[serde(default = "default_albedo")]
+    albedo: [f64; 3],
+    #[serde(default)]
+    anisotropy: f64,
+}
+
+// A medium filling all the space between shapes, out to `distance` from wherever a ray sets out.
+// Rays that travel further without scattering reach the background
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FogDesc {
+    density: f64,
+    #[serde(default = "default_albedo")]
+    albedo: [f64; 3],
+    #[serde(default)]
+    anisotropy: f64,
+    distance: f64,
+}
+
+fn default_albedo() -> [f64; 3] {
+    [1., 1., 1.]
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ShapeDesc {
+    // Moves in a straight line from `center` at time 0 to `end_center` at time 1 if one is given
+    Sphere {
+        center: [f64; 3],
+        end_center: Option<[f64; 3]>,
+        radius: f64,
+        material: String,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        normals: Option<[[f64; 3]; 3]>,
+        uvs: Option<[[f64; 2]; 3]>,
+        material: String,
+    },
+    Plane {
+        point: [f64; 3],
+        normal: [f64; 3],
+        material: String,
+    },
+    Disk {
+        center: [f64; 3],
+        normal: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    // A parallelogram with one corner at `corner` and sides `u` and `v`
+    Quad {
+        corner: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        material: String,
+    },
+    // A box spanned by two opposite corners, turned about its center by `rotation`, which is
+    // degrees about the x, y and z axes in that order
+    Box {
+        min: [f64; 3],
+        max: [f64; 3],
+        #[serde(default)]
+        rotation: [f64; 3],
+        material: String,
+    },
+    // Quadrics stand on `base` and extend along `axis`. Cones, paraboloids and hyperboloids can
+    // be clipped to a `span` of heights along the axis, and all of them to a `sweep` in degrees
+    // around it. They are closed with flat caps unless `capped` is false
+    Cylinder {
+        base: [f64; 3],
+        #[serde(default = "default_axis")]
+        axis: [f64; 3],
+        radius: f64,
+        height: f64,
+        #[serde(default = "default_sweep")]
+        sweep: f64,
+        #[serde(default = "default_capped")]
+        capped: bool,
+        material: String,
+    },
+    // Narrows from `radius` at the base to a point at `height`
+    Cone {
+        base: [f64; 3],
+        #[serde(default = "default_axis")]
+        axis: [f64; 3],
+        radius: f64,
+        height: f64,
+        span: Option<[f64; 2]>,
+        #[serde(default = "default_sweep")]
+        sweep: f64,
+        #[serde(default = "default_capped")]
+        capped: bool,
+        material: String,
+    },
+    // Widens from a point at the base to `radius` at `height`
+    Paraboloid {
+        base: [f64; 3],
+        #[serde(default = "default_axis")]
+        axis: [f64; 3],
+        radius: f64,
+        height: f64,
+        span: Option<[f64; 2]>,
+        #[serde(default = "default_sweep")]
+        sweep: f64,
+        #[serde(default = "default_capped")]
+        capped: bool,
+        material: String,
+    },
+    // Widens from `radius` at the base to `end_radius` at `height` above and below it
+    Hyperboloid {
+        base: [f64; 3],
+        #[serde(default = "default_axis")]
+        axis: [f64; 3],
+        radius: f64,
+        end_radius: f64,
+        height: f64,
+        span: Option<[f64; 2]>,
+        #[serde(default = "default_sweep")]
+        sweep: f64,
+        #[serde(default = "default_capped")]
+        capped: bool,
+        material: String,
+    },
+    // A tube of `minor_radius` bent into a ring of `major_radius` around `axis`
+    Torus {
+        center: [f64; 3],
+        #[serde(default = "default_axis")]
+        axis: [f64; 3],
+        major_radius: f64,
+        minor_radius: f64,
+        material: String,
+    },
+    // Solids combined from two closed shapes `a` and `b`, given as tables of their own
+    Union {
+        a: Box<ShapeDesc>,
+        b: Box<ShapeDesc>,
+    },
+    Intersection {
+        a: Box<ShapeDesc>,
+        b: Box<ShapeDesc>,
+    },
+    // What is inside `a` but not `b`
+    Difference {
+        a: Box<ShapeDesc>,
+        b: Box<ShapeDesc>,
+    },
+    Instance(InstanceDesc),
+    Mesh {
+        // Path to a Wavefront .obj, .ply, .stl, .gltf or .glb file, relative to the scene file
+        path: PathBuf,
+        // Replaces the materials from the model's material libraries
+        material: Option<String>,
+    },
+}
+
+fn default_axis() -> [f64; 3] {
+    [0., 1., 0.]
+}
+
+// A copy of one of the scene's named `objects`, which is scaled, then rotated by degrees about the
+// x, y and z axes in that order, then translated, and finally transformed by `matrix` if one is
+// given. Every instance of an object shares its geometry. An instance can instead move through
+// `keyframes`, each placing the object the same way at a given time
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InstanceDesc {
+    object: String,
+    #[serde(default = "default_scale")]
+    scale: [f64; 3],
+    #[serde(default)]
+    rotation: [f64; 3],
+    #[serde(default)]
+    translate: [f64; 3],
+    // An affine transform, by rows
+    matrix: Option<[[f64; 4]; 4]>,
+    #[serde(default)]
+    keyframes: Vec<KeyframeDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyframeDesc {
+    time: f64,
+    #[serde(default = "default_scale")]
+    scale: [f64; 3],
+    #[serde(default)]
+    rotation: [f64; 3],
+    #[serde(default)]
+    translate: [f64; 3],
+}
+
+fn default_scale() -> [f64; 3] {
+    [1., 1., 1.]
+}
+
+fn default_sweep() -> f64 {
+    360.
+}
+
+fn default_capped() -> bool {
+    true
+}
+
+// Load a TOML scene file, or a glTF file to render as it is
+pub fn load(path: &Path) -> Result<Scene, SceneError> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    if let Some("gltf") | Some("glb") = extension.as_deref() {
+        return from_gltf(path, gltf::load(path)?);
+    }
+    let source = fs::read_to_string(path)?;
+    parse(&source, path.parent().unwrap_or_else(|| Path::new("")))
+}
+
+// Render a glTF scene from its own camera with default settings. Files without a camera are
+// viewed from in front, far enough back to see everything
+fn from_gltf(path: &Path, scene: GltfScene) -> Result<Scene, SceneError> {
+    let camera = match scene.camera {
+        Some(camera) => camera,
+        None => {
+            let bbox = scene.world.bounding_box();
+            if bbox.min.x() > bbox.max.x() {
+                return Err(SceneError::Model(MeshError::Invalid {
+                    file: path.to_path_buf(),
+                    message: "file has no geometry and no camera".into(),
+                }));
+            }
+            let radius = ((bbox.max - bbox.min).length() / 2.).max(1e-3);
+            let vertical_fov = default_vertical_fov();
+            let distance = radius / (vertical_fov.to_radians() / 2.).sin();
+            GltfCamera {
+                look_from: bbox.centroid() + Vector3::new(0., 0., distance),
+                look_at: bbox.centroid(),
+                up: Vector3::new(0., 1., 0.),
+                vertical_fov,
+                aspect_ratio: None,
+            }
+        }
+    };
+    Ok(Scene {
+        world: scene.world,
+        camera: CameraSettings {
+            aspect_ratio: camera.aspect_ratio.unwrap_or_else(default_aspect_ratio),
+            image_height: DEFAULT_IMAGE_HEIGHT,
+            image_width: None,
+            vertical_fov: camera.vertical_fov,
+            look_from: camera.look_from,
+            look_at: camera.look_at,
+            up: camera.up,
+            defocus_angle: 0.,
+            focus_distance: default_focus_distance(),
+            shutter_open: 0.,
+            shutter_close: 0.,
+            samples: default_samples(),
+        },
+        render: RenderSettings::default(),
+    })
+}
+
+// Parse a scene, resolving any files it refers to relative to `directory`
+pub fn parse(source: &str, directory: &Path) -> Result<Scene, SceneError> {
+    let desc: SceneDesc = toml::from_str(source).map_err(|err| SceneError::Syntax {
+        line: err.span().map_or(1, |span| line_number(source, span.start)),
+        message: err.message().to_string(),
+    })?;
+    let invalid = |span: Range<usize>, message: String| SceneError::Invalid {
+        line: line_number(source, span.start),
+        message,
+    };
+
+    let camera = desc.camera.get_ref();
+    let invalid_camera = |message: String| invalid(desc.camera.span(), message);
+    if camera.image_height == 0 {
+        return Err(invalid_camera("image_height must be positive".into()));
+    }
+    positive("aspect_ratio", camera.aspect_ratio).map_err(&invalid_camera)?;
+    if !(camera.vertical_fov > 0. && camera.vertical_fov < 180.) {
+        return Err(invalid_camera(
+            "vertical_fov must be between 0 and 180 degrees".into(),
+        ));
+    }
+    finite("look_from", &camera.look_from).map_err(&invalid_camera)?;
+    finite("look_at", &camera.look_at).map_err(&invalid_camera)?;
+    finite("up", &camera.up).map_err(&invalid_camera)?;
+    if camera.look_from == camera.look_at {
+        return Err(invalid(
+            desc.camera.span(),
+            "look_from and look_at must be different points".into(),
+        ));
+    }
+    if (point(&camera.look_at) - point(&camera.look_from))
+        .cross(point(&camera.up))
+        .near_zero()
+    {
+        return Err(invalid(
+            desc.camera.span(),
+            "up must not be parallel to the view direction".into(),
+        ));
+    }
+    if !(camera.defocus_angle >= 0. && camera.defocus_angle < 180.) {
+        return Err(invalid_camera(
+            "defocus_angle must be at least 0 and less than 180 degrees".into(),
+        ));
+    }
+    positive("focus_distance", camera.focus_distance).map_err(&invalid_camera)?;
+    finite("shutter times", &camera.shutter).map_err(&invalid_camera)?;
+    if camera.shutter[0] > camera.shutter[1] {
+        return Err(invalid_camera(
+            "the shutter must not close before it opens".into(),
+        ));
+    }
+    let render = match &desc.render {
+        Some(render) if render.get_ref().samples == 0 => {
+            return Err(invalid(render.span(), "samples must be positive".into()));
+        }
+        Some(render) if render.get_ref().threads == Some(0) => {
+            return Err(invalid(render.span(), "threads must be positive".into()));
+        }
+        Some(render) if render.get_ref().max_depth == Some(0) => {
+            return Err(invalid(render.span(), "max_depth must be positive".into()));
+        }
+        Some(render) => render.get_ref(),
+        None => &RenderDesc {
+            samples: default_samples(),
+            threads: None,
+            seed: None,
+            max_depth: None,
+        },
+    };
+
+    for material in desc.materials.values() {
+        material
+            .get_ref()
+            .check()
+            .map_err(|message| invalid(material.span(), message))?;
+    }
+
+    let background: Arc<dyn Background> = match &desc.background {
+        None => Arc::new(GradientBackground::sky()),
+        Some(background) => match background.get_ref() {
+            BackgroundDesc::Solid { color: c } => {
+                finite("color", c).map_err(|message| invalid(background.span(), message))?;
+                Arc::new(SolidBackground { color: color(c) })
+            }
+            BackgroundDesc::Gradient { bottom, top } => {
+                finite("bottom", bottom)
+                    .and_then(|_| finite("top", top))
+                    .map_err(|message| invalid(background.span(), message))?;
+                Arc::new(GradientBackground {
+                    bottom: color(bottom),
+                    top: color(top),
+                })
+            }
+            BackgroundDesc::Environment {
+                path,
+                intensity,
+                rotation,
+            } => {
+                if !(*intensity >= 0. && intensity.is_finite()) {
+                    return Err(invalid(
+                        background.span(),
+                        "intensity must be finite and not negative".into(),
+                    ));
+                }
+                finite("rotation", &[*rotation])
+                    .map_err(|message| invalid(background.span(), message))?;
+                let path = directory.join(path);
+                match EnvironmentMap::load(&path, *intensity, *rotation) {
+                    Ok(map) => Arc::new(map),
+                    Err(err) => {
+                        return Err(invalid(
+                            background.span(),
+                            format!("cannot load environment {}: {}", path.display(), err),
+                        ))
+                    }
+                }
+            }
+        },
+    };
+
+    let fog = match &desc.fog {
+        Some(fog) => {
+            let desc = fog.get_ref();
+            let medium = MediumDesc {
+                density: desc.density,
+                albedo: desc.albedo,
+                anisotropy: desc.anisotropy,
+            };
+            medium
+                .check()
+                .and_then(|_| positive("distance", desc.distance))
+                .map_err(|message| invalid(fog.span(), message))?;
+            Some(Fog {
+                medium: medium.build(),
+                distance: desc.distance,
+            })
+        }
+        None => None,
+    };
+
+    let mut builder = ShapeBuilder {
+        directory,
+        materials: &desc.materials,
+        objects: HashMap::new(),
+    };
+    // Objects are built before the shapes that place them, and cannot place one another
+    let mut objects = HashMap::new();
+    for (name, object) in &desc.objects {
+        let line = line_number(source, object.span().start);
+        if let ShapeDesc::Instance(_) = object.get_ref() {
+            return Err(SceneError::Invalid {
+                line,
+                message: "objects cannot be instances of other objects".into(),
+            });
+        }
+        objects.insert(
+            name.clone(),
+            Arc::from(builder.build(object.get_ref(), line)?),
+        );
+    }
+    builder.objects = objects;
+    let mut world = World::new();
+    for shape in &desc.shapes {
+        let line = line_number(source, shape.span().start);
+        // Instances go in the world's top-level hierarchy, so that they can be moved later
+        match shape.get_ref() {
+            ShapeDesc::Instance(instance) => world.add_instance(builder.instance(instance, line)?),
+            shape => world.add(builder.build(shape, line)?),
+        }
+    }
+
+    Ok(Scene {
+        world,
+        camera: CameraSettings {
+            aspect_ratio: camera.aspect_ratio,
+            image_height: camera.image_height,
+            image_width: None,
+            vertical_fov: camera.vertical_fov,
+            look_from: point(&camera.look_from),
+            look_at: point(&camera.look_at),
+            up: point(&camera.up),
+            defocus_angle: camera.defocus_angle,
+            focus_distance: camera.focus_distance,
+            shutter_open: camera.shutter[0],
+            shutter_close: camera.shutter[1],
+            samples: render.samples,
+        },
+        render: {
+            let defaults = RenderSettings::default();
+            RenderSettings {
+                threads: render.threads.unwrap_or(defaults.threads),
+                seed: render.seed.unwrap_or(defaults.seed),
+                max_depth: render.max_depth.unwrap_or(defaults.max_depth),
+                background,
+                fog,
+            }
+        },
+    })
+}
+
+// Builds shapes from their descriptions, with the scene's materials and named objects for them to
+// refer to
+struct ShapeBuilder<'a> {
+    directory: &'a Path,
+    materials: &'a HashMap<String, Spanned<MaterialDesc>>,
+    objects: HashMap<String, Arc<dyn Hittable>>,
+}
+
+impl ShapeBuilder<'_> {
+    fn instance(&self, desc: &InstanceDesc, line: usize) -> Result<Instance, SceneError> {
+        let invalid = |message: String| SceneError::Invalid { line, message };
+        let object = match self.objects.get(&desc.object) {
+            Some(object) => object.clone(),
+            None => return Err(invalid(format!("unknown object '{}'", desc.object))),
+        };
+        if !desc.keyframes.is_empty() {
+            if desc.scale != default_scale()
+                || desc.rotation != [0.; 3]
+                || desc.translate != [0.; 3]
+                || desc.matrix.is_some()
+            {
+                return Err(invalid(
+                    "keyframes cannot be combined with scale, rotation, translate or matrix".into(),
+                ));
+            }
+            for keyframe in &desc.keyframes {
+                finite("keyframe times", &[keyframe.time]).map_err(&invalid)?;
+                finite("keyframe scale", &keyframe.scale).map_err(&invalid)?;
+                finite("keyframe rotation", &keyframe.rotation).map_err(&invalid)?;
+                finite("keyframe translate", &keyframe.translate).map_err(&invalid)?;
+            }
+            let keyframes = desc
+                .keyframes
+                .iter()
+                .map(|keyframe| Keyframe {
+                    time: keyframe.time,
+                    scale: point(&keyframe.scale),
+                    rotation: point(&keyframe.rotation),
+                    translate: point(&keyframe.translate),
+                })
+                .collect();
+            return Instance::animated(object, keyframes).ok_or_else(|| {
+                invalid("keyframe scales must not be zero or change sign along any axis".into())
+            });
+        }
+        finite("scale", &desc.scale).map_err(&invalid)?;
+        finite("rotation", &desc.rotation).map_err(&invalid)?;
+        finite("translate", &desc.translate).map_err(&invalid)?;
+        if let Some(rows) = &desc.matrix {
+            finite("matrix", &rows.concat()).map_err(&invalid)?;
+        }
+        let matrix = match desc.matrix {
+            Some(rows) if rows[3] != [0., 0., 0., 1.] => {
+                return Err(invalid(
+                    "matrix must be affine, with a last row of [0, 0, 0, 1]".into(),
+                ))
+            }
+            Some(rows) => Matrix4(rows),
+            None => Matrix4::identity(),
+        };
+        let transform = matrix
+            * Matrix4::translation(point(&desc.translate))
+            * Matrix4::euler_rotation(point(&desc.rotation))
+            * Matrix4::scale(point(&desc.scale));
+        Instance::new(object, transform)
+            .ok_or_else(|| invalid("transform must not be singular".into()))
+    }
+
+    // Build a shape, reporting any errors at the given line. Shapes combined by CSG are built
+    // recursively, and their operands' errors are reported at the line of the whole CSG shape, as
+    // nested tables carry no position of their own
+    fn build(&self, shape: &ShapeDesc, line: usize) -> Result<Box<dyn Hittable>, SceneError> {
+        let invalid = |message: String| SceneError::Invalid { line, message };
+        let material = |name: &str| match self.materials.get(name) {
+            Some(m) => Ok(m.get_ref().build()),
+            None => Err(invalid(format!("unknown material '{}'", name))),
+        };
+        let csg =
+            |operation, a: &ShapeDesc, b: &ShapeDesc| -> Result<Box<dyn Hittable>, SceneError> {
+                Ok(Box::new(Csg {
+                    operation,
+                    a: self.build(a, line)?,
+                    b: self.build(b, line)?,
+                }))
+            };
+        let built: Box<dyn Hittable> = match shape {
+            ShapeDesc::Sphere {
+                center,
+                end_center,
+                radius,
+                material: name,
+            } => {
+                finite("center", center).map_err(&invalid)?;
+                if let Some(end) = end_center {
+                    finite("end_center", end).map_err(&invalid)?;
+                }
+                positive("radius", *radius).map_err(&invalid)?;
+                match end_center {
+                    Some(end) => Box::new(MovingSphere {
+                        start: point(center),
+                        end: point(end),
+                        radius: *radius,
+                        material: material(name)?,
+                    }),
+                    None => Box::new(Sphere {
+                        center: point(center),
+                        radius: *radius,
+                        material: material(name)?,
+                    }),
+                }
+            }
+            ShapeDesc::Plane {
+                point: p,
+                normal,
+                material: name,
+            } => {
+                finite("point", p).map_err(&invalid)?;
+                finite("normal", normal).map_err(&invalid)?;
+                if point(normal).near_zero() {
+                    return Err(invalid("normal must not be zero".into()));
+                }
+                Box::new(Plane {
+                    point: point(p),
+                    normal: point(normal),
+                    material: material(name)?,
+                })
+            }
+            ShapeDesc::Disk {
+                center,
+                normal,
+                radius,
+                material: name,
+            } => {
+                finite("center", center).map_err(&invalid)?;
+                finite("normal", normal).map_err(&invalid)?;
+                if point(normal).near_zero() {
+                    return Err(invalid("normal must not be zero".into()));
+                }
+                positive("radius", *radius).map_err(&invalid)?;
+                Box::new(Disk {
+                    center: point(center),
+                    normal: point(normal),
+                    radius: *radius,
+                    material: material(name)?,
+                })
+            }
+            ShapeDesc::Quad {
+                corner,
+                u,
+                v,
+                material: name,
+            } => {
+                finite("corner", corner).map_err(&invalid)?;
+                finite("u", u).map_err(&invalid)?;
+                finite("v", v).map_err(&invalid)?;
+                if point(u).cross(point(v)).near_zero() {
+                    return Err(invalid("quad has no area".into()));
+                }
+                Box::new(Quad {
+                    corner: point(corner),
+                    u: point(u),
+                    v: point(v),
+                    material: material(name)?,
+                })
+            }
+            ShapeDesc::Box {
+                min,
+                max,
+                rotation,
+                material: name,
+            } => {
+                finite("min", min).map_err(&invalid)?;
+                finite("max", max).map_err(&invalid)?;
+                finite("rotation", rotation).map_err(&invalid)?;
+                if (0..3).any(|axis| min[axis] >= max[axis]) {
+                    return Err(invalid(
+                        "max must be greater than min along every axis".into(),
+                    ));
+                }
+                Box::new(
+                    Cuboid::new(point(min), point(max), material(name)?)
+                        .rotated(&Matrix4::euler_rotation(point(rotation))),
+                )
+            }
+            ShapeDesc::Triangle {
+                vertices,
+                normals,
+                uvs,
+                material: name,
+            } => {
+                finite("vertices", &vertices.concat()).map_err(&invalid)?;
+                if let Some(normals) = normals {
+                    finite("normals", &normals.concat()).map_err(&invalid)?;
+                }
+                if let Some(uvs) = uvs {
+                    finite("uvs", &uvs.concat()).map_err(&invalid)?;
+                }
+                let vertices = [
+                    point(&vertices[0]),
+                    point(&vertices[1]),
+                    point(&vertices[2]),
+                ];
+                if (vertices[1] - vertices[0])
+                    .cross(vertices[2] - vertices[0])
+                    .near_zero()
+                {
+                    return Err(invalid("triangle has no area".into()));
+                }
+                Box::new(Triangle {
+                    vertices,
+                    normals: normals.map(|n| [point(&n[0]), point(&n[1]), point(&n[2])]),
+                    uvs: uvs.map(|uv| {
+                        [
+                            (uv[0][0], uv[0][1]),
+                            (uv[1][0], uv[1][1]),
+                            (uv[2][0], uv[2][1]),
+                        ]
+                    }),
+                    material: material(name)?,
+                })
+            }
+            ShapeDesc::Cylinder {
+                base,
+                axis,
+                radius,
+                height,
+                sweep,
+                capped,
+                material: name,
+            } => {
+                check_quadric(base, axis, *radius, *height, *sweep).map_err(&invalid)?;
+                Box::new(Cylinder {
+                    base: point(base),
+                    axis: point(axis),
+                    radius: *radius,
+                    height: *height,
+                    sweep: *sweep,
+                    capped: *capped,
+                    material: material(name)?,
+                })
+            }
+            ShapeDesc::Cone {
+                base,
+                axis,
+                radius,
+                height,
+                span,
+                sweep,
+                capped,
+                material: name,
+            } => {
+                check_quadric(base, axis, *radius, *height, *sweep).map_err(&invalid)?;
+                let span = quadric_span(*span, [0., *height], 0. ..=*height).map_err(&invalid)?;
+                Box::new(Cone {
+                    base: point(base),
+                    axis: point(axis),
+                    radius: *radius,
+                    height: *height,
+                    span,
+                    sweep: *sweep,
+                    capped: *capped,
+                    material: material(name)?,
+                })
+            }
+            ShapeDesc::Paraboloid {
+                base,
+                axis,
+                radius,
+                height,
+                span,
+                sweep,
+                capped,
+                material: name,
+            } => {
+                check_quadric(base, axis, *radius, *height, *sweep).map_err(&invalid)?;
+                let span =
+                    quadric_span(*span, [0., *height], 0. ..=f64::INFINITY).map_err(&invalid)?;
+                Box::new(Paraboloid {
+                    base: point(base),
+                    axis: point(axis),
+                    radius: *radius,
+                    height: *height,
+                    span,
+                    sweep: *sweep,
+                    capped: *capped,
+                    material: material(name)?,
+                })
+            }
+            ShapeDesc::Hyperboloid {
+                base,
+                axis,
+                radius,
+                end_radius,
+                height,
+                span,
+                sweep,
+                capped,
+                material: name,
+            } => {
+                check_quadric(base, axis, *radius, *height, *sweep).map_err(&invalid)?;
+                positive("end_radius", *end_radius).map_err(&invalid)?;
+                if end_radius <= radius {
+                    return Err(invalid("end_radius must be greater than radius".into()));
+                }
+                let span = quadric_span(
+                    *span,
+                    [-*height, *height],
+                    f64::NEG_INFINITY..=f64::INFINITY,
+                )
+                .map_err(&invalid)?;
+                Box::new(Hyperboloid {
+                    base: point(base),
+                    axis: point(axis),
+                    radius: *radius,
+                    end_radius: *end_radius,
+                    height: *height,
+                    span,
+                    sweep: *sweep,
+                    capped: *capped,
+                    material: material(name)?,
+                })
+            }
+            ShapeDesc::Torus {
+                center,
+                axis,
+                major_radius,
+                minor_radius,
+                material: name,
+            } => {
+                finite("center", center).map_err(&invalid)?;
+                finite("axis", axis).map_err(&invalid)?;
+                if point(axis).near_zero() {
+                    return Err(invalid("axis must not be zero".into()));
+                }
+                positive("major_radius", *major_radius).map_err(&invalid)?;
+                positive("minor_radius", *minor_radius).map_err(&invalid)?;
+                Box::new(Torus {
+                    center: point(center),
+                    axis: point(axis),
+                    major_radius: *major_radius,
+                    minor_radius: *minor_radius,
+                    material: material(name)?,
+                })
+            }
+            ShapeDesc::Union { a, b } => csg(CsgOperation::Union, a, b)?,
+            ShapeDesc::Intersection { a, b } => csg(CsgOperation::Intersection, a, b)?,
+            ShapeDesc::Difference { a, b } => csg(CsgOperation::Difference, a, b)?,
+            ShapeDesc::Instance(instance) => Box::new(self.instance(instance, line)?),
+            ShapeDesc::Mesh {
+                path,
+                material: name,
+            } => {
+                let path = self.directory.join(path);
+                let mut data = match mesh::load(&path) {
+                    Ok(data) => data,
+                    Err(err) => {
+                        return Err(invalid(format!(
+                            "cannot load mesh {}: {}",
+                            path.display(),
+                            err
+                        )))
+                    }
+                };
+                if let Some(name) = name {
+                    data.set_material(material(name)?);
+                }
+                Box::new(Mesh::new(data))
+            }
+        };
+        Ok(built)
+    }
+}
+
+// Check the fields shared by every quadric
+fn check_quadric(
+    base: &[f64; 3],
+    axis: &[f64; 3],
+    radius: f64,
+    height: f64,
+    sweep: f64,
+) -> Result<(), String> {
+    finite("base", base)?;
+    finite("axis", axis)?;
+    if point(axis).near_zero() {
+        return Err("axis must not be zero".into());
+    }
+    positive("radius", radius)?;
+    positive("height", height)?;
+    if !(sweep > 0. && sweep <= 360.) {
+        return Err("sweep must be more than 0 and at most 360 degrees".into());
+    }
+    Ok(())
+}
+
+// The heights to keep of a quadric, which must be within the heights where the shape exists
+fn quadric_span(
+    span: Option<[f64; 2]>,
+    default: [f64; 2],
+    limits: RangeInclusive<f64>,
+) -> Result<Range<f64>, String> {
+    let [start, end] = span.unwrap_or(default);
+    finite("span", &[start, end])?;
+    if start >= end {
+        Err("span must be increasing".into())
+    } else if !limits.contains(&start) || !limits.contains(&end) {
+        Err(format!(
+            "span must be within {} to {}",
+            limits.start(),
+            limits.end()
+        ))
+    } else {
+        Ok(start..end)
+    }
+}
+
+impl MaterialDesc {
+    fn check(&self) -> Result<(), String> {
+        match self {
+            MaterialDesc::Lambertian { albedo } => finite("albedo", albedo),
+            MaterialDesc::Mirror { albedo, fuzziness } => {
+                finite("albedo", albedo)?;
+                if !(*fuzziness >= 0. && fuzziness.is_finite()) {
+                    return Err("fuzziness must be finite and not negative".into());
+                }
+                Ok(())
+            }
+            MaterialDesc::Dialectric { refractive_index } => {
+                positive("refractive_index", *refractive_index)
+            }
+            MaterialDesc::DiffuseLight { emit } => finite("emit", emit),
+            MaterialDesc::Medium(medium) => medium.check(),
+        }
+    }
+
+    fn build(&self) -> Box<dyn Material> {
+        match self {
+            MaterialDesc::Lambertian { albedo } => Box::new(LambertianMaterial {
+                albedo: color(albedo),
+            }),
+            MaterialDesc::Mirror { albedo, fuzziness } => Box::new(MirrorMaterial {
+                albedo: color(albedo),
+                fuzziness: *fuzziness,
+            }),
+            MaterialDesc::Dialectric { refractive_index } => Box::new(DialectricMaterial {
+                refractive_index: *refractive_index,
+            }),
+            MaterialDesc::DiffuseLight { emit } => Box::new(DiffuseLight { emit: color(emit) }),
+            MaterialDesc::Medium(medium) => Box::new(medium.build()),
+        }
+    }
+}
+
+impl MediumDesc {
+    fn check(&self) -> Result<(), String> {
+        positive("density", self.density)?;
+        if self.anisotropy.is_nan() || self.anisotropy.abs() >= 1. {
+            return Err("anisotropy must be between -1 and 1".into());
+        }
+        finite("albedo", &self.albedo)
+    }
+
+    fn build(&self) -> Medium {
+        Medium {
+            density: self.density,
+            albedo: color(&self.albedo),
+            phase: if self.anisotropy == 0. {
+                PhaseFunction::Isotropic
+            } else {
+                PhaseFunction::HenyeyGreenstein { g: self.anisotropy }
+            },
+        }
+    }
+}
+
+// TOML allows numbers that are infinite or not a number. These pass comparisons such as `<= 0.`
+// and only show up later as blank pixels or panics, so every number in a scene is checked with
+// one of these
+
+// Check that a number is finite and greater than zero
+fn positive(name: &str, value: f64) -> Result<(), String> {
+    if value > 0. && value.is_finite() {
+        Ok(())
+    } else {
+        Err(format!("{} must be positive", name))
+    }
+}
+
+// Check that a point, vector, color or list of numbers is finite
+fn finite(name: &str, values: &[f64]) -> Result<(), String> {
+    if values.iter().all(|value| value.is_finite()) {
+        Ok(())
+    } else {
+        Err(format!("{} must be finite", name))
+    }
+}
+
+fn point(v: &[f64; 3]) -> Point3 {
+    Point3::new(v[0], v[1], v[2])
+}
+
+fn color(v: &[f64; 3]) -> Color3 {
+    Color3::new(v[0], v[1], v[2])
+}
+
+// Convert a byte offset into the source to a 1-based line number
+fn line_number(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count() + 1
+}
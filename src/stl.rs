@@ -0,0 +1,161 @@
+use std::{collections::HashMap, fs, path::Path, str};
+
+use crate::{
+    mesh::{self, MeshData, MeshError, MeshTriangle},
+    vector::Point3,
+};
+
+// Size of the header and of each triangle record of a binary STL file, in bytes
+const BINARY_HEADER_SIZE: usize = 84;
+const BINARY_TRIANGLE_SIZE: usize = 50;
+
+// Load an STL model in ASCII or binary form. STL stores every triangle separately, so vertices
+// at the same position are merged to share them between triangles. The facet normals are often
+// missing or wrong, so the normals are taken from the winding of the vertices instead
+pub fn load(path: &Path) -> Result<MeshData, MeshError> {
+    let bytes = fs::read(path)?;
+    parse(&bytes, path)
+}
+
+// Parse the contents of an STL file. `path` is only used in error messages
+pub fn parse(bytes: &[u8], path: &Path) -> Result<MeshData, MeshError> {
+    let mut builder = Builder::default();
+    if is_binary(bytes) {
+        parse_binary(bytes, path, &mut builder)?;
+    } else {
+        let source = str::from_utf8(bytes).map_err(|_| MeshError::Invalid {
+            file: path.to_path_buf(),
+            message: "ASCII STL file is not valid text".into(),
+        })?;
+        parse_ascii(source, path, &mut builder)?;
+    }
+    let mut mesh = builder.mesh;
+    mesh.materials.push(mesh::default_material());
+    Ok(mesh)
+}
+
+// ASCII files start with "solid", but so do the headers of some binary files, so trust the
+// triangle count of a binary header when it matches the size of the file
+fn is_binary(bytes: &[u8]) -> bool {
+    if bytes.len() >= BINARY_HEADER_SIZE {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+        if BINARY_HEADER_SIZE + count * BINARY_TRIANGLE_SIZE == bytes.len() {
+            return true;
+        }
+    }
+    let start = bytes.iter().position(|b| !b.is_ascii_whitespace());
+    !start.is_some_and(|start| bytes[start..].starts_with(b"solid"))
+}
+
+fn parse_binary(bytes: &[u8], path: &Path, builder: &mut Builder) -> Result<(), MeshError> {
+    let invalid = |message: String| MeshError::Invalid {
+        file: path.to_path_buf(),
+        message,
+    };
+    if bytes.len() < BINARY_HEADER_SIZE {
+        return Err(invalid("file is too short for a binary STL header".into()));
+    }
+    let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+    let records = &bytes[BINARY_HEADER_SIZE..];
+    if records.len() < count * BINARY_TRIANGLE_SIZE {
+        return Err(invalid(format!(
+            "file is truncated, the header promises {} triangles but there is only room for {}",
+            count,
+            records.len() / BINARY_TRIANGLE_SIZE
+        )));
+    }
+
+    let float = |b: &[u8]| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64;
+    for record in records.chunks_exact(BINARY_TRIANGLE_SIZE).take(count) {
+        // Each record is a facet normal, three vertices and a two byte attribute
+        let vertex = |i: usize| {
+            let b = &record[12 + i * 12..];
+            Point3::new(float(b), float(&b[4..]), float(&b[8..]))
+        };
+        builder.add_triangle([vertex(0), vertex(1), vertex(2)]);
+    }
+    Ok(())
+}
+
+fn parse_ascii(source: &str, path: &Path, builder: &mut Builder) -> Result<(), MeshError> {
+    let mut facet: Option<Vec<Point3>> = None;
+    let mut in_solid = false;
+    for (number, line) in source.lines().enumerate() {
+        let syntax = |message: String| MeshError::Syntax {
+            file: path.to_path_buf(),
+            line: number + 1,
+            message,
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            [] => {}
+            ["solid", ..] if !in_solid => in_solid = true,
+            ["facet", ..] if in_solid && facet.is_none() => facet = Some(Vec::new()),
+            ["outer", "loop"] | ["endloop"] => {}
+            ["vertex", x, y, z] => {
+                let parse = |v: &str| {
+                    v.parse::<f64>()
+                        .map_err(|_| syntax(format!("invalid number '{}'", v)))
+                };
+                let point = Point3::new(parse(x)?, parse(y)?, parse(z)?);
+                facet
+                    .as_mut()
+                    .ok_or_else(|| syntax("vertex outside of a facet".into()))?
+                    .push(point);
+            }
+            ["endfacet"] => {
+                let vertices = facet
+                    .take()
+                    .ok_or_else(|| syntax("endfacet without a facet".into()))?;
+                if vertices.len() < 3 {
+                    return Err(syntax("a facet needs at least three vertices".into()));
+                }
+                for i in 1..vertices.len() - 1 {
+                    builder.add_triangle([vertices[0], vertices[i], vertices[i + 1]]);
+                }
+            }
+            ["endsolid", ..] if in_solid && facet.is_none() => in_solid = false,
+            _ => return Err(syntax(format!("unexpected '{}'", line.trim()))),
+        }
+    }
+    if in_solid {
+        return Err(MeshError::Invalid {
+            file: path.to_path_buf(),
+            message: "file is truncated, it ends inside a solid".into(),
+        });
+    }
+    Ok(())
+}
+
+// Collects triangles into a mesh, merging vertices at identical positions
+#[derive(Default)]
+struct Builder {
+    mesh: MeshData,
+    vertices: HashMap<[u64; 3], usize>,
+}
+
+impl Builder {
+    fn add_triangle(&mut self, vertices: [Point3; 3]) {
+        let positions = vertices.map(|vertex| self.vertex(vertex));
+        self.mesh.triangles.push(MeshTriangle {
+            positions,
+            normals: None,
+            uvs: None,
+            colors: None,
+            material: 0,
+        });
+    }
+
+    fn vertex(&mut self, point: Point3) -> usize {
+        let key = [
+            point.x().to_bits(),
+            point.y().to_bits(),
+            point.z().to_bits(),
+        ];
+        let positions = &mut self.mesh.positions;
+        *self.vertices.entry(key).or_insert_with(|| {
+            positions.push(point);
+            positions.len() - 1
+        })
+    }
+}
@@ -0,0 +1,34 @@
+use crate::{canvas::Canvas, vector::Color3};
+
+// An image mapped onto a surface by its texture coordinates. The image repeats outside the range
+// 0 to 1, and v runs up the image as in OBJ files, so (0, 0) is its bottom left corner
+pub struct ImageTexture {
+    image: Canvas,
+}
+
+impl ImageTexture {
+    // The image must hold linear values and must not be empty
+    pub fn new(image: Canvas) -> Self {
+        assert!(
+            image.width > 0 && image.height > 0,
+            "texture image is empty"
+        );
+        Self { image }
+    }
+
+    // Color at the given texture coordinates, blended between the four nearest pixels
+    pub fn sample(&self, (u, v): (f64, f64)) -> Color3 {
+        let x = u * self.image.width as f64 - 0.5;
+        let y = (1. - v) * self.image.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let pixel = |i: f64, j: f64| {
+            let i = (i as i64).rem_euclid(self.image.width as i64) as u32;
+            let j = (j as i64).rem_euclid(self.image.height as i64) as u32;
+            *self.image.get_pixel(i, j)
+        };
+        let top = pixel(x0, y0) * (1. - tx) + pixel(x0 + 1., y0) * tx;
+        let bottom = pixel(x0, y0 + 1.) * (1. - tx) + pixel(x0 + 1., y0 + 1.) * tx;
+        top * (1. - ty) + bottom * ty
+    }
+}
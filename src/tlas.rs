@@ -0,0 +1,168 @@
+use std::ops::Range;
+use std::option::Option;
+use std::vec::Vec;
+
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable};
+use crate::instance::Instance;
+use crate::ray::Ray;
+
+// Most instances a leaf holds before it is split
+const MAX_LEAF_SIZE: usize = 2;
+
+// Deepest tree the traversal stack has room for. Splitting at the median halves the instances at
+// every level, so this is far more than any scene needs
+const MAX_DEPTH: usize = 64;
+
+// The top level of a two-level acceleration structure: a hierarchy over instances, each placing
+// shared geometry that carries its own bottom-level hierarchy, such as a mesh. Only the instances'
+// boxes are stored here, so memory grows with the amount of unique geometry rather than the number
+// of copies, and after instances are moved the top level can be rebuilt in time proportional to
+// the number of instances without touching the geometry
+#[derive(Default)]
+pub struct Tlas {
+    // Kept in the order of the leaves, so each leaf refers to a run of them
+    instances: Vec<Instance>,
+    // Nodes in depth-first order, with the root first
+    nodes: Vec<TlasNode>,
+}
+
+struct TlasNode {
+    bbox: Aabb,
+    // A leaf holds `count` instances starting at `first`. Interior nodes have a count of zero,
+    // their left child directly after them in the list and their right child at `first`
+    first: usize,
+    count: usize,
+}
+
+impl Tlas {
+    pub fn new(instances: Vec<Instance>) -> Self {
+        let mut tlas = Self {
+            instances,
+            nodes: Vec::new(),
+        };
+        tlas.rebuild();
+        tlas
+    }
+
+    // Add an instance. Until the next rebuild, every instance is tested against every ray
+    pub fn push(&mut self, instance: Instance) {
+        self.instances.push(instance);
+        self.nodes.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    // Instances can be moved through this. As the hierarchy may no longer fit them, it is
+    // dropped, and every instance is tested against every ray until the next rebuild. The order
+    // of the instances changes with every rebuild
+    pub fn instances_mut(&mut self) -> &mut [Instance] {
+        self.nodes.clear();
+        &mut self.instances
+    }
+
+    // Rebuild the hierarchy over the instances' current boxes
+    pub fn rebuild(&mut self) {
+        self.nodes.clear();
+        if !self.instances.is_empty() {
+            self.build_node(0..self.instances.len());
+        }
+    }
+
+    // Build the subtree over a run of instances, splitting it at the median centroid along the
+    // axis where the centroids are most spread out. Returns the index of the subtree's root
+    fn build_node(&mut self, range: Range<usize>) -> usize {
+        let instances = &mut self.instances[range.clone()];
+        let bbox = instances.iter().fold(Aabb::empty(), |bbox, instance| {
+            bbox.union(instance.bounding_box())
+        });
+        let index = self.nodes.len();
+        self.nodes.push(TlasNode {
+            bbox,
+            first: range.start,
+            count: range.len(),
+        });
+        if range.len() <= MAX_LEAF_SIZE {
+            return index;
+        }
+
+        let axis = instances
+            .iter()
+            .fold(Aabb::empty(), |bounds, instance| {
+                let centroid = instance.bounding_box().centroid();
+                bounds.union(Aabb::new(centroid, centroid))
+            })
+            .longest_axis();
+        let half = range.len() / 2;
+        instances.select_nth_unstable_by(half, |a, b| {
+            let a = a.bounding_box().centroid().axis(axis);
+            let b = b.bounding_box().centroid().axis(axis);
+            a.total_cmp(&b)
+        });
+
+        let middle = range.start + half;
+        self.build_node(range.start..middle);
+        let right = self.build_node(middle..range.end);
+        self.nodes[index].first = right;
+        self.nodes[index].count = 0;
+        index
+    }
+}
+
+impl Hittable for Tlas {
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>> {
+        if self.nodes.is_empty() {
+            return self.instances.iter().fold(None, |closest, instance| {
+                let end = closest.map_or(range.end, |hit: Hit| hit.distance);
+                instance.hit(ray, &(range.start..end)).or(closest)
+            });
+        }
+        // Walk the tree with an explicit stack, narrowing the range to each hit found so that
+        // boxes behind it are skipped
+        let mut range = range.clone();
+        let mut closest = None;
+        let mut stack = [0; MAX_DEPTH];
+        let mut depth = 1;
+        while depth > 0 {
+            depth -= 1;
+            let index = stack[depth];
+            let node = &self.nodes[index];
+            if !node.bbox.hit(ray, &range) {
+                continue;
+            }
+            if node.count > 0 {
+                for instance in &self.instances[node.first..node.first + node.count] {
+                    if let Some(hit) = instance.hit(ray, &range) {
+                        range.end = hit.distance;
+                        closest = Some(hit);
+                    }
+                }
+            } else {
+                // Visit the left child first
+                stack[depth] = node.first;
+                stack[depth + 1] = index + 1;
+                depth += 2;
+            }
+        }
+        closest
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        match self.nodes.first() {
+            Some(root) => root.bbox,
+            None => self.instances.iter().fold(Aabb::empty(), |bbox, instance| {
+                bbox.union(instance.bounding_box())
+            }),
+        }
+    }
+}
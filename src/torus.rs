@@ -0,0 +1,225 @@
+use std::f64::consts::PI;
+use std::ops::Range;
+use std::option::Option;
+
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable};
+use crate::material::Material;
+use crate::planar::tangent_basis;
+use crate::polynomial;
+use crate::ray::Ray;
+use crate::vector::{Point3, Vector3};
+
+// A ring-shaped tube around `axis`. The tube's center runs in a circle of `major_radius` around
+// `center`, and the tube itself has `minor_radius`. Texture coordinates run around the axis in u
+// and around the tube in v, starting from its outer edge
+pub struct Torus {
+    pub center: Point3,
+    pub axis: Vector3,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+    pub material: Box<dyn Material>,
+}
+
+impl Hittable for Torus {
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>> {
+        let axis = self.axis.unit();
+        let (tangent, bitangent) = tangent_basis(axis);
+        let local = |v: Vector3| Vector3::new(v.dot(tangent), v.dot(axis), v.dot(bitangent));
+
+        // Work in units of distance along the ray, so the size of the coefficients depends only
+        // on the size of the torus
+        let scale = ray.direction.length();
+        let direction = local(ray.direction / scale);
+        let mut origin = local(ray.origin - self.center);
+
+        // Clip the range to the bounding sphere and move the origin up to where it starts. Far
+        // away origins would otherwise give huge coefficients whose rounding errors swamp the roots
+        let (big, small) = (self.major_radius, self.minor_radius);
+        // The torus touches the sphere around its outer edge, so the sphere is made a little larger
+        // to keep roots there inside the interval searched
+        let bound = (big + small) * (1. + 1e-9);
+        let b = origin.dot(direction);
+        let discriminant = b * b - (origin.length_squared() - bound * bound);
+        if discriminant <= 0. {
+            return None;
+        }
+        let start = (range.start * scale).max(-b - discriminant.sqrt());
+        let end = (range.end * scale).min(-b + discriminant.sqrt());
+        if start >= end {
+            return None;
+        }
+        origin += direction * start;
+
+        // Substituting the ray into (|p|² + R² - r²)² = 4 R² (x² + z²) gives a quartic in the
+        // distance along it
+        let (o, d) = (origin, direction);
+        let n = o.dot(d);
+        let k = o.length_squared() + big * big - small * small;
+        let four_r2 = 4. * big * big;
+        let coefficients = [
+            k * k - four_r2 * (o.x() * o.x() + o.z() * o.z()),
+            4. * n * k - 2. * four_r2 * (o.x() * d.x() + o.z() * d.z()),
+            4. * n * n + 2. * k - four_r2 * (d.x() * d.x() + d.z() * d.z()),
+            4. * n,
+            1.,
+        ];
+        let s = *polynomial::roots(&coefficients, 0. ..end - start).first()?;
+        let t = (start + s) / scale;
+        if !range.contains(&t) {
+            return None;
+        }
+
+        // The normal points away from the nearest point on the circle through the tube's center
+        let p = o + d * s;
+        let ring_distance = (p.x() * p.x() + p.z() * p.z()).sqrt();
+        let ring = Vector3::new(p.x(), 0., p.z()) * (big / ring_distance);
+        let n = (p - ring).unit();
+        let normal = tangent * n.x() + axis * n.y() + bitangent * n.z();
+        let u = p.z().atan2(p.x()).rem_euclid(2. * PI) / (2. * PI);
+        let v = p.y().atan2(ring_distance - big).rem_euclid(2. * PI) / (2. * PI);
+        Some(Hit {
+            point: ray.at(t),
+            normal,
+            geometric_normal: normal,
+            distance: t,
+            uv: (u, v),
+            barycentric: None,
+            color: None,
+            material: self.material.as_ref(),
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // Along each axis the ring extends as a disk does, and the tube adds its radius on top
+        let axis = self.axis.unit();
+        let ring = |a: f64| (1. - a * a).max(0.).sqrt() * self.major_radius + self.minor_radius;
+        let extent = Vector3::new(ring(axis.x()), ring(axis.y()), ring(axis.z()));
+        Aabb::new(self.center - extent, self.center + extent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::LambertianMaterial;
+    use crate::vector::Color3;
+
+    fn torus(center: Point3, axis: Vector3) -> Torus {
+        Torus {
+            center,
+            axis,
+            major_radius: 2.,
+            minor_radius: 0.5,
+            material: Box::new(LambertianMaterial {
+                albedo: Color3::new(0.5, 0.5, 0.5),
+            }),
+        }
+    }
+
+    fn assert_hit(hit: Option<Hit>, distance: f64, normal: Vector3) {
+        let hit = hit.expect("ray should hit the torus");
+        assert!(
+            (hit.distance - distance).abs() < 1e-9,
+            "distance {} != {}",
+            hit.distance,
+            distance
+        );
+        assert!(
+            (hit.normal - normal).length() < 1e-9,
+            "normal {:?} != {:?}",
+            hit.normal,
+            normal
+        );
+    }
+
+    #[test]
+    fn hit_parallel_to_axis() {
+        let torus = torus(Point3::new(0., 0., 0.), Vector3::new(0., 1., 0.));
+        let range = 0. ..f64::INFINITY;
+        // Down onto the top of the tube, and up onto its bottom
+        let down = Ray::new(Point3::new(2., 5., 0.), Vector3::new(0., -1., 0.), 0.);
+        assert_hit(torus.hit(&down, &range), 4.5, Vector3::new(0., 1., 0.));
+        let up = Ray::new(Point3::new(0., -5., -2.), Vector3::new(0., 1., 0.), 0.);
+        assert_hit(torus.hit(&up, &range), 4.5, Vector3::new(0., -1., 0.));
+        // Through the hole along the axis itself
+        let through = Ray::new(Point3::new(0., 5., 0.), Vector3::new(0., -1., 0.), 0.);
+        assert!(torus.hit(&through, &range).is_none());
+    }
+
+    #[test]
+    fn hit_in_equatorial_plane() {
+        let torus = torus(Point3::new(0., 0., 0.), Vector3::new(0., 1., 0.));
+        let ray = Ray::new(Point3::new(5., 0., 0.), Vector3::new(-1., 0., 0.), 0.);
+        // The ray crosses the tube on both sides of the hole: in at 2.5 and out at 1.5, then in at
+        // -1.5 and out at -2.5
+        assert_hit(
+            torus.hit(&ray, &(0. ..f64::INFINITY)),
+            2.5,
+            Vector3::new(1., 0., 0.),
+        );
+        assert_hit(
+            torus.hit(&ray, &(3. ..f64::INFINITY)),
+            3.5,
+            Vector3::new(-1., 0., 0.),
+        );
+        assert_hit(
+            torus.hit(&ray, &(4. ..f64::INFINITY)),
+            6.5,
+            Vector3::new(1., 0., 0.),
+        );
+        assert_hit(
+            torus.hit(&ray, &(7. ..f64::INFINITY)),
+            7.5,
+            Vector3::new(-1., 0., 0.),
+        );
+        assert!(torus.hit(&ray, &(8. ..f64::INFINITY)).is_none());
+        assert!(torus.hit(&ray, &(0. ..2.)).is_none());
+    }
+
+    #[test]
+    fn hit_from_inside_the_hole() {
+        let torus = torus(Point3::new(0., 0., 0.), Vector3::new(0., 1., 0.));
+        let ray = Ray::new(Point3::new(0., 0., 0.), Vector3::new(0., 0., 1.), 0.);
+        assert_hit(
+            torus.hit(&ray, &(0. ..f64::INFINITY)),
+            1.5,
+            Vector3::new(0., 0., -1.),
+        );
+    }
+
+    #[test]
+    fn hit_moved_and_turned() {
+        // Standing on its edge around the x axis, away from the origin
+        let center = Point3::new(10., -3., 4.);
+        let torus = torus(center, Vector3::new(1., 0., 0.));
+        let range = 0. ..f64::INFINITY;
+        let along_axis = Ray::new(
+            center + Vector3::new(-6., 0., 2.),
+            Vector3::new(1., 0., 0.),
+            0.,
+        );
+        assert_hit(
+            torus.hit(&along_axis, &range),
+            5.5,
+            Vector3::new(-1., 0., 0.),
+        );
+        let in_plane = Ray::new(
+            center + Vector3::new(0., 0., 6.),
+            Vector3::new(0., 0., -1.),
+            0.,
+        );
+        assert_hit(torus.hit(&in_plane, &range), 3.5, Vector3::new(0., 0., 1.));
+    }
+
+    #[test]
+    fn hit_from_far_away() {
+        let torus = torus(Point3::new(0., 0., 0.), Vector3::new(0., 1., 0.));
+        let ray = Ray::new(Point3::new(1e5, 0., 0.), Vector3::new(-1., 0., 0.), 0.);
+        assert_hit(
+            torus.hit(&ray, &(0. ..f64::INFINITY)),
+            1e5 - 2.5,
+            Vector3::new(1., 0., 0.),
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use std::ops::Range;
+use std::option::Option;
+
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vector::{Point3, Vector3};
+
+// Rays closer to parallel with the plane of a triangle than this are treated as missing it. It is
+// relative to the lengths of the edges and the ray direction, so that tiny triangles and rays in
+// the scaled space of an instance are treated like any other
+const PARALLEL_EPSILON: f64 = 1e-12;
+
+// A single flat triangle. The front face is the one from which the vertices appear
+// counter-clockwise, and the geometric normal points out of it
+pub struct Triangle {
+    pub vertices: [Point3; 3],
+    // Per-vertex normals, interpolated across the triangle to give smooth shading
+    pub normals: Option<[Vector3; 3]>,
+    pub uvs: Option<[(f64, f64); 3]>,
+    pub material: Box<dyn Material>,
+}
+
+// Möller–Trumbore ray-triangle intersection. Returns the distance along the ray and the
+// barycentric weights of the second and third vertices
+pub fn intersect_triangle(
+    ray: &Ray,
+    vertices: &[Point3; 3],
+    range: &Range<f64>,
+) -> Option<(f64, f64, f64)> {
+    let edge1 = vertices[1] - vertices[0];
+    let edge2 = vertices[2] - vertices[0];
+    let p = ray.direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    // The determinant is at most the product of the three lengths, which are compared squared to
+    // save taking roots
+    let scale = edge1.length_squared() * edge2.length_squared() * ray.direction.length_squared();
+    if determinant * determinant <= PARALLEL_EPSILON * PARALLEL_EPSILON * scale {
+        return None;
+    }
+    let inverse_determinant = 1. / determinant;
+
+    let origin_offset = ray.origin - vertices[0];
+    let u = origin_offset.dot(p) * inverse_determinant;
+    if !(0. ..=1.).contains(&u) {
+        return None;
+    }
+    let q = origin_offset.cross(edge1);
+    let v = ray.direction.dot(q) * inverse_determinant;
+    if v < 0. || u + v > 1. {
+        return None;
+    }
+
+    let t = edge2.dot(q) * inverse_determinant;
+    if !range.contains(&t) {
+        return None;
+    }
+    Some((t, u, v))
+}
+
+// Fill in a hit on a triangle from its barycentric coordinates, interpolating normals and UVs
+pub fn triangle_hit<'a>(
+    ray: &Ray,
+    vertices: &[Point3; 3],
+    normals: Option<&[Vector3; 3]>,
+    uvs: Option<&[(f64, f64); 3]>,
+    (t, u, v): (f64, f64, f64),
+    material: &'a dyn Material,
+) -> Hit<'a> {
+    let weights = [1. - u - v, u, v];
+    let geometric_normal = (vertices[1] - vertices[0])
+        .cross(vertices[2] - vertices[0])
+        .unit();
+    let normal = match normals {
+        Some(n) => {
+            let interpolated = n[0] * weights[0] + n[1] * weights[1] + n[2] * weights[2];
+            if interpolated.near_zero() {
+                geometric_normal
+            } else {
+                interpolated.unit()
+            }
+        }
+        None => geometric_normal,
+    };
+    let uv = match uvs {
+        Some(uvs) => (
+            uvs[0].0 * weights[0] + uvs[1].0 * weights[1] + uvs[2].0 * weights[2],
+            uvs[0].1 * weights[0] + uvs[1].1 * weights[1] + uvs[2].1 * weights[2],
+        ),
+        None => (u, v),
+    };
+    Hit {
+        point: ray.at(t),
+        normal,
+        geometric_normal,
+        distance: t,
+        uv,
+        barycentric: Some(weights),
+        color: None,
+        material,
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, range: &Range<f64>) -> Option<Hit<'_>> {
+        let coordinates = intersect_triangle(ray, &self.vertices, range)?;
+        Some(triangle_hit(
+            ray,
+            &self.vertices,
+            self.normals.as_ref(),
+            self.uvs.as_ref(),
+            coordinates,
+            self.material.as_ref(),
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let [a, b, c] = self.vertices;
+        Aabb::new(a.min(b).min(c), a.max(b).max(c))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hit_tiny_triangle() {
+        // Edges of ten micrometres in a scene in metres, seen along a ray as short as the local
+        // direction of an instance scaled up a thousand times
+        let vertices = [
+            Point3::new(0., 0., 0.),
+            Point3::new(1e-5, 0., 0.),
+            Point3::new(0., 1e-5, 0.),
+        ];
+        let ray = Ray {
+            origin: Point3::new(2e-6, 2e-6, 1.),
+            direction: Vector3::new(0., 0., -1e-3),
+            time: 0.,
+        };
+        let (t, u, v) = intersect_triangle(&ray, &vertices, &(0. ..f64::INFINITY)).unwrap();
+        assert!((t - 1e3).abs() < 1e-6);
+        assert!((u - 0.2).abs() < 1e-9 && (v - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn miss_parallel_ray() {
+        let vertices = [
+            Point3::new(0., 0., 0.),
+            Point3::new(1., 0., 0.),
+            Point3::new(0., 1., 0.),
+        ];
+        let ray = Ray::new(Point3::new(-1., 0.2, 0.), Vector3::new(1., 0., 0.), 0.);
+        assert!(intersect_triangle(&ray, &vertices, &(0. ..f64::INFINITY)).is_none());
+    }
+}
@@ -3,7 +3,7 @@ use std::{
     ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign},
 };
 
-use rand::{rngs::ThreadRng, Rng};
+use rand::{Rng, RngCore};
 
 const TWO_PI: f64 = 2. * std::f64::consts::PI;
 const EPSILON: f64 = 1e-8;
@@ -117,6 +117,25 @@ impl Vector3 {
         self.2
     }
 
+    // Access a component by index, where 0, 1 and 2 are the x, y and z axes
+    pub fn axis(&self, axis: usize) -> f64 {
+        match axis {
+            0 => self.0,
+            1 => self.1,
+            2 => self.2,
+            _ => panic!("invalid axis {}", axis),
+        }
+    }
+
+    // Component-wise minimum and maximum of two vectors
+    pub fn min(self, rhs: Self) -> Self {
+        Self(self.0.min(rhs.0), self.1.min(rhs.1), self.2.min(rhs.2))
+    }
+
+    pub fn max(self, rhs: Self) -> Self {
+        Self(self.0.max(rhs.0), self.1.max(rhs.1), self.2.max(rhs.2))
+    }
+
     pub fn length_squared(self) -> f64 {
         self.0 * self.0 + self.1 * self.1 + self.2 * self.2
     }
@@ -141,14 +160,13 @@ impl Vector3 {
         self / self.length()
     }
 
-    pub fn rand_unit(rng: &mut rand::rngs::ThreadRng) -> Self {
-        let alpha = rng.gen_range(0. ..TWO_PI);
-        let beta = rng.gen_range(0. ..TWO_PI);
-        Self::new(
-            alpha.sin() * beta.cos(),
-            alpha.sin() * beta.sin(),
-            alpha.cos(),
-        )
+    // A direction chosen uniformly over the unit sphere. Picking the height uniformly (rather than
+    // the polar angle) is what makes the distribution uniform, by Archimedes' hat-box theorem
+    pub fn rand_unit(rng: &mut dyn RngCore) -> Self {
+        let z: f64 = rng.gen_range(-1. ..1.);
+        let phi = rng.gen_range(0. ..TWO_PI);
+        let r = (1. - z * z).sqrt();
+        Self::new(r * phi.cos(), r * phi.sin(), z)
     }
 
     pub fn near_zero(&self) -> bool {
@@ -159,7 +177,7 @@ impl Vector3 {
         self - normal * 2. * self.dot(normal)
     }
 
-    pub fn random(rng: &mut ThreadRng) -> Vector3 {
+    pub fn random(rng: &mut dyn RngCore) -> Vector3 {
         Self::new(
             rng.gen_range(0. ..1.),
             rng.gen_range(0. ..1.),
@@ -167,7 +185,7 @@ impl Vector3 {
         )
     }
 
-    pub fn random_range(rng: &mut ThreadRng, min: f64, max: f64) -> Vector3 {
+    pub fn random_range(rng: &mut dyn RngCore, min: f64, max: f64) -> Vector3 {
         Self::new(
             rng.gen_range(min..max),
             rng.gen_range(min..max),